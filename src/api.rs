@@ -0,0 +1,517 @@
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::{FromRequest, Json, MatchedPath, Request, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use log::error;
+use serde::Deserialize;
+use sqlx::Either;
+use std::sync::Arc;
+
+use crate::aws::{
+    BatchExecuteStatementInputDef, BatchExecuteStatementOutputDef, BeginTransactionInputDef,
+    BeginTransactionOutputDef, CommitTransactionInputDef, CommitTransactionOutputDef,
+    ExecuteStatementInputDef, ExecuteStatementOutputDef, RollbackTransactionInputDef,
+    RollbackTransactionOutputDef, SqlParameterDef,
+};
+use crate::cluster::{Cluster, Clusters};
+use crate::error::query_error_response;
+use crate::query::{QueryOutput, run_query};
+use crate::statements::StatementLog;
+use crate::transactions::{TransactionError, Transactions};
+
+macro_rules! get_or_400 {
+    ($input:expr, $field:ident) => {
+        match &$input.$field {
+            Some(value) => value,
+            None => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Missing required field: {}", stringify!($field)),
+                )
+                    .into_response())
+            }
+        }
+    };
+}
+
+/// What the Data API routes run statements on
+#[derive(Clone)]
+struct ApiState {
+    clusters: Arc<Clusters>,
+    transactions: Arc<Transactions>,
+    statements: Arc<StatementLog>,
+}
+
+/// The cluster a request with `resource_arn` is routed to
+fn find_cluster(
+    clusters: &Clusters,
+    resource_arn: Option<&str>,
+) -> Result<Arc<Cluster>, (StatusCode, String)> {
+    clusters.get(resource_arn).cloned().ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "No cluster found for resourceArn '{}'",
+                resource_arn.unwrap_or_default()
+            ),
+        )
+    })
+}
+
+/// Run `sql` in the transaction `transaction_id` if set, on the cluster of `resource_arn`
+/// otherwise
+async fn run(
+    state: &ApiState,
+    resource_arn: Option<&str>,
+    transaction_id: Option<&str>,
+    database: Option<String>,
+    schema: Option<String>,
+    sql: &str,
+    params: Vec<Vec<SqlParameterDef>>,
+) -> Result<QueryOutput, Response> {
+    let result = match transaction_id {
+        Some(id) => state.transactions.run_query(id, sql, params).await,
+        None => {
+            let cluster =
+                find_cluster(&state.clusters, resource_arn).map_err(IntoResponse::into_response)?;
+            run_query(&cluster, database, schema, sql, params, &state.statements)
+                .await
+                .map_err(TransactionError::Query)
+        }
+    };
+    result.map_err(|e| {
+        if let TransactionError::Query((_, e)) = &e {
+            error!("Error executing statement: {e}");
+        }
+        e.into_response()
+    })
+}
+
+async fn execute_statement(
+    State(state): State<ApiState>,
+    Json(input): Json<ExecuteStatementInputDef>,
+) -> Result<Json<ExecuteStatementOutputDef>, Response> {
+    let sql = get_or_400!(input, sql);
+    let params = input.parameters.unwrap_or(vec![]);
+
+    let output = match run(
+        &state,
+        input.resource_arn.as_deref(),
+        input.transaction_id.as_deref(),
+        input.database,
+        input.schema,
+        sql,
+        vec![params],
+    )
+    .await?
+    {
+        Either::Left(records) => ExecuteStatementOutputDef {
+            records: Some(records.rows),
+            column_metadata: records
+                .column_metadata
+                .filter(|_| input.include_result_metadata == Some(true)),
+            ..ExecuteStatementOutputDef::default()
+        },
+        Either::Right(affected_rows) => ExecuteStatementOutputDef {
+            number_of_records_updated: affected_rows as i64,
+            ..ExecuteStatementOutputDef::default()
+        },
+    };
+
+    Ok(Json(output))
+}
+
+async fn batch_execute_statement(
+    State(state): State<ApiState>,
+    Json(input): Json<BatchExecuteStatementInputDef>,
+) -> Result<Json<BatchExecuteStatementOutputDef>, Response> {
+    let sql = get_or_400!(input, sql);
+    let params = input.parameter_sets.unwrap_or(vec![]);
+
+    run(
+        &state,
+        input.resource_arn.as_deref(),
+        input.transaction_id.as_deref(),
+        input.database,
+        input.schema,
+        sql,
+        params,
+    )
+    .await?;
+
+    Ok(Json(BatchExecuteStatementOutputDef::default()))
+}
+
+async fn begin_transaction(
+    State(state): State<ApiState>,
+    Json(input): Json<BeginTransactionInputDef>,
+) -> Result<Json<BeginTransactionOutputDef>, Response> {
+    let cluster = find_cluster(&state.clusters, input.resource_arn.as_deref())
+        .map_err(IntoResponse::into_response)?;
+    let id = state
+        .transactions
+        .begin(
+            cluster,
+            input.database.as_deref(),
+            input.schema.as_deref(),
+            input.secret_arn.as_deref(),
+        )
+        .await
+        .map_err(|(status, e)| {
+            error!("Error starting transaction: {e}");
+            query_error_response((status, e))
+        })?;
+
+    Ok(Json(BeginTransactionOutputDef {
+        transaction_id: Some(id),
+    }))
+}
+
+async fn commit_transaction(
+    State(state): State<ApiState>,
+    Json(input): Json<CommitTransactionInputDef>,
+) -> Result<Json<CommitTransactionOutputDef>, Response> {
+    let id = get_or_400!(input, transaction_id);
+    state
+        .transactions
+        .end(id, true)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(CommitTransactionOutputDef {
+        transaction_status: Some("Transaction Committed".to_string()),
+    }))
+}
+
+async fn rollback_transaction(
+    State(state): State<ApiState>,
+    Json(input): Json<RollbackTransactionInputDef>,
+) -> Result<Json<RollbackTransactionOutputDef>, Response> {
+    let id = get_or_400!(input, transaction_id);
+    state
+        .transactions
+        .end(id, false)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(RollbackTransactionOutputDef {
+        transaction_status: Some("Rollback Complete".to_string()),
+    }))
+}
+
+/// Name of the Data API action served at `path`, e.g. `ExecuteStatement` for `/Execute`
+pub(crate) fn action_name(path: &str) -> Option<&'static str> {
+    match path {
+        "/Execute" => Some("ExecuteStatement"),
+        "/BatchExecute" => Some("BatchExecuteStatement"),
+        "/BeginTransaction" => Some("BeginTransaction"),
+        "/CommitTransaction" => Some("CommitTransaction"),
+        "/RollbackTransaction" => Some("RollbackTransaction"),
+        _ => None,
+    }
+}
+
+/// Name of the Data API action `request` was routed to, for middlewares
+pub(crate) fn request_action(request: &Request) -> &'static str {
+    request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|path| action_name(path.as_str()))
+        .unwrap_or("Unknown")
+}
+
+/// Read the body of `request` so that a middleware can look into it, returning it along
+/// with the request to pass on. Bodies larger than the `DefaultBodyLimit` of the router,
+/// `MAX_REQUEST_SIZE` for the Data API, are answered with a 413.
+pub(crate) async fn buffer_body(request: Request) -> Result<(Request, Bytes), Response> {
+    let (parts, body) = request.into_parts();
+    let bytes = Bytes::from_request(Request::from_parts(parts.clone(), body), &())
+        .await
+        .map_err(IntoResponse::into_response)?;
+    Ok((Request::from_parts(parts, Body::from(bytes.clone())), bytes))
+}
+
+/// The fields of a Data API request its middlewares look at, read from the body once by
+/// `read_fields` and kept in the request extensions
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RequestFields {
+    pub(crate) resource_arn: Option<String>,
+    pub(crate) secret_arn: Option<String>,
+    pub(crate) database: Option<String>,
+    pub(crate) sql: Option<String>,
+}
+
+/// Read the `RequestFields` of `request` into its extensions, for the middlewares after the
+/// one calling it. The handlers report invalid bodies, so they leave the fields empty.
+pub(crate) async fn read_fields(request: Request) -> Result<Request, Response> {
+    let (mut request, body) = buffer_body(request).await?;
+    let fields = serde_json::from_slice::<RequestFields>(&body).unwrap_or_default();
+    request.extensions_mut().insert(fields);
+    Ok(request)
+}
+
+/// The `RequestFields` read from `request`, all empty if they weren't read
+pub(crate) fn request_fields(request: &Request) -> &RequestFields {
+    static EMPTY: RequestFields = RequestFields {
+        resource_arn: None,
+        secret_arn: None,
+        database: None,
+        sql: None,
+    };
+    request.extensions().get().unwrap_or(&EMPTY)
+}
+
+/// Largest request body the Data API accepts
+pub(crate) const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// Routes of the Data API, running statements on `clusters`
+pub(crate) fn router(
+    clusters: Arc<Clusters>,
+    transactions: Arc<Transactions>,
+    statements: Arc<StatementLog>,
+) -> Router {
+    Router::new()
+        .route("/Execute", post(execute_statement))
+        .route("/BatchExecute", post(batch_execute_statement))
+        .route("/BeginTransaction", post(begin_transaction))
+        .route("/CommitTransaction", post(commit_transaction))
+        .route("/RollbackTransaction", post(rollback_transaction))
+        .with_state(ApiState {
+            clusters,
+            transactions,
+            statements,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use crate::backend::SqliteBackend;
+    use crate::config::{PoolConfig, TransactionsConfig};
+    use crate::test_util::call;
+
+    fn router(clusters: Clusters) -> Router {
+        let statements = Arc::new(StatementLog::new(0));
+        let transactions = Transactions::new(TransactionsConfig::default(), statements.clone());
+        super::router(Arc::new(clusters), Arc::new(transactions), statements)
+    }
+
+    fn sqlite_router() -> Router {
+        let mut clusters = Clusters::default();
+        clusters.add(Cluster {
+            name: "default".to_string(),
+            resource_arn: None,
+            backend: Arc::new(SqliteBackend::in_memory()),
+        });
+        router(clusters)
+    }
+
+    #[tokio::test]
+    async fn test_execute_and_batch_execute_on_sqlite() {
+        let app = sqlite_router();
+
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({
+                "database": "testdb",
+                "sql": "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT, score REAL, ok BOOLEAN, data BLOB)"
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/BatchExecute",
+            json!({
+                "database": "testdb",
+                "sql": "INSERT INTO t (name, score, ok, data) VALUES (:name, :score, :ok, :data)",
+                "parameterSets": [
+                    [
+                        {"name": "name", "value": {"stringValue": "a"}},
+                        {"name": "score", "value": {"doubleValue": 1.5}},
+                        {"name": "ok", "value": {"booleanValue": true}},
+                        {"name": "data", "value": {"blobValue": "3q0="}}
+                    ],
+                    [
+                        {"name": "name", "value": {"stringValue": "b"}},
+                        {"name": "score", "value": {"isNull": true}},
+                        {"name": "ok", "value": {"booleanValue": false}},
+                        {"name": "data", "value": {"isNull": true}}
+                    ]
+                ]
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({
+                "database": "testdb",
+                "sql": "SELECT id, name, score, ok, data FROM t WHERE id >= :min ORDER BY id",
+                "parameters": [{"name": "min", "value": {"longValue": 1}}]
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["records"],
+            json!([
+                [
+                    {"longValue": 1},
+                    {"stringValue": "a"},
+                    {"doubleValue": 1.5},
+                    {"booleanValue": true},
+                    {"blobValue": "3q0="}
+                ],
+                [
+                    {"longValue": 2},
+                    {"stringValue": "b"},
+                    {"isNull": true},
+                    {"booleanValue": false},
+                    {"isNull": true}
+                ]
+            ])
+        );
+
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"database": "testdb", "sql": "DELETE FROM t WHERE name = 'a'"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["numberOfRecordsUpdated"], json!(1));
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_databases_are_separate() {
+        let app = sqlite_router();
+
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"database": "one", "sql": "CREATE TABLE t (id INTEGER)"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"database": "two", "sql": "SELECT * FROM t"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body.as_str().unwrap().contains("no such table"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn test_missing_parameter() {
+        let app = sqlite_router();
+
+        let (status, body) = call(&app, "POST", "/Execute", json!({"sql": "SELECT :x"})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, json!("Missing parameter: x"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_routed_by_resource_arn() {
+        let mut clusters = Clusters::default();
+        for (name, resource_arn) in [("default", None), ("orders", Some("arn:orders"))] {
+            clusters.add(Cluster {
+                name: name.to_string(),
+                resource_arn: resource_arn.map(str::to_string),
+                backend: Arc::new(SqliteBackend::in_memory()),
+            });
+        }
+        let app = router(clusters);
+
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"resourceArn": "arn:orders", "sql": "CREATE TABLE orders (id INTEGER)"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"resourceArn": "arn:orders", "sql": "SELECT * FROM orders"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Unknown ARNs go to the default cluster, which doesn't have the table
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"resourceArn": "arn:other", "sql": "SELECT * FROM orders"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_pool_exhaustion() {
+        let pool = PoolConfig {
+            acquire_timeout_ms: 50,
+            ..PoolConfig::default()
+        };
+        let mut clusters = Clusters::default();
+        clusters.add(Cluster {
+            name: "default".to_string(),
+            resource_arn: None,
+            backend: Arc::new(
+                SqliteBackend::connect_lazy_with_pool("sqlite::memory:", &pool).unwrap(),
+            ),
+        });
+        let app = router(clusters);
+
+        // An in-memory database has a single connection, taken by the transaction
+        let (status, body) = call(&app, "POST", "/BeginTransaction", json!({})).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+
+        let request = Request::post("/Execute")
+            .header("content-type", "application/json")
+            .body(Body::from(json!({"sql": "SELECT 1"}).to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers()[crate::error::ERROR_TYPE_HEADER],
+            "ServiceUnavailableError"
+        );
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            body["message"],
+            "All 1 connections of the pool are in use, none was released within 50ms"
+        );
+    }
+}
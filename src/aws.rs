@@ -9,21 +9,26 @@ use std::vec::Vec;
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExecuteStatementInputDef {
+    pub resource_arn: Option<String>,
+    pub secret_arn: Option<String>,
     pub sql: Option<String>,
     pub database: Option<String>,
     pub schema: Option<String>,
     pub parameters: Option<Vec<SqlParameterDef>>,
+    pub transaction_id: Option<String>,
+    pub include_result_metadata: Option<bool>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SqlParameterDef {
     pub name: String,
     pub value: FieldDef,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub type_hint: Option<TypeHintDef>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum FieldDef {
     ArrayValue(ArrayValueDef),
@@ -35,7 +40,7 @@ pub enum FieldDef {
     StringValue(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum ArrayValueDef {
     ArrayValues(Vec<ArrayValueDef>),
@@ -45,7 +50,7 @@ pub enum ArrayValueDef {
     StringValues(Vec<String>),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum TypeHintDef {
     #[serde(rename = "DATE")]
@@ -72,8 +77,8 @@ pub struct ExecuteStatementOutputDef {
     pub formatted_records: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
 pub struct ColumnMetadataDef {
     pub name: Option<String>,
     pub r#type: i32,
@@ -94,10 +99,13 @@ pub struct ColumnMetadataDef {
 #[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BatchExecuteStatementInputDef {
+    pub resource_arn: Option<String>,
+    pub secret_arn: Option<String>,
     pub sql: Option<String>,
     pub database: Option<String>,
     pub schema: Option<String>,
     pub parameter_sets: Option<Vec<Vec<SqlParameterDef>>>,
+    pub transaction_id: Option<String>,
 }
 
 #[derive(Debug, Default, Serialize)]
@@ -111,3 +119,46 @@ pub struct BatchExecuteStatementOutputDef {
 pub struct UpdateResultDef {
     pub generated_fields: Option<Vec<FieldDef>>,
 }
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BeginTransactionInputDef {
+    pub resource_arn: Option<String>,
+    pub secret_arn: Option<String>,
+    pub database: Option<String>,
+    pub schema: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BeginTransactionOutputDef {
+    pub transaction_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitTransactionInputDef {
+    pub resource_arn: Option<String>,
+    pub secret_arn: Option<String>,
+    pub transaction_id: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitTransactionOutputDef {
+    pub transaction_status: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RollbackTransactionInputDef {
+    pub resource_arn: Option<String>,
+    pub secret_arn: Option<String>,
+    pub transaction_id: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RollbackTransactionOutputDef {
+    pub transaction_status: Option<String>,
+}
@@ -0,0 +1,245 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use log::{error, warn};
+use sqlx::mysql::MySqlConnectOptions;
+use sqlx::pool::PoolOptions;
+use sqlx::postgres::PgConnectOptions;
+
+use crate::aws::SqlParameterDef;
+use crate::config::PoolConfig;
+use crate::error::AwsError;
+use crate::query::{Dialect, QueryError, QueryOutput};
+
+mod mock;
+mod mysql;
+mod postgres;
+mod sqlite;
+
+pub use mock::MockBackend;
+pub use mysql::MySqlBackend;
+pub use postgres::PostgresBackend;
+pub use sqlite::SqliteBackend;
+
+/// A database engine that Data API statements are run against
+#[async_trait]
+pub trait Backend: Send + Sync {
+    /// The dialect of its statements, deciding how they're lexed and which placeholders named
+    /// parameters must be rewritten into before calling `run_query`
+    fn dialect(&self) -> Dialect;
+
+    /// Run an already rewritten statement once for each set of parameters in `params`.
+    /// `args_to_be_bound` lists, in order, which parameter goes into each placeholder.
+    async fn run_query(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, QueryError>;
+
+    /// Take a connection switched to `database` and `schema` and start a transaction on it.
+    /// The connection is kept by the transaction until it ends.
+    async fn begin_transaction(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+    ) -> Result<Box<dyn Transaction>, QueryError>;
+
+    /// Run `sql` on `database` as it is, without preparing it, e.g. a statement of a
+    /// fixture script
+    async fn execute_raw(&self, database: &str, sql: &str) -> Result<()>;
+
+    /// Create `database` if it doesn't exist yet
+    async fn create_database(&self, database: &str) -> Result<()>;
+
+    /// Create `database` as a copy of `template`, replacing it if it exists
+    async fn clone_database(&self, template: &str, database: &str) -> Result<()>;
+
+    /// Delete `database` and everything in it, if it exists
+    async fn drop_database(&self, database: &str) -> Result<()>;
+
+    /// Save the current content of `database`, replacing its previous snapshot if any
+    async fn snapshot_database(&self, database: &str) -> Result<()>;
+
+    /// Bring `database` back to the content saved by `snapshot_database`
+    async fn restore_database(&self, database: &str) -> Result<()>;
+
+    /// Check the database can be reached, by running `SELECT 1`
+    async fn ping(&self) -> Result<()>;
+
+    /// Open a connection outside of the pools and close it, failing with the reason right
+    /// away, whereas the pools keep retrying refused connections until their acquire timeout
+    async fn check_connection(&self) -> Result<()> {
+        self.ping().await
+    }
+
+    /// Current usage of the connection pools, summed over all of them
+    fn pool_stats(&self) -> PoolStats;
+
+    /// Close the connection pools, waiting for the connections in use to be returned, when the
+    /// server shuts down
+    async fn close(&self);
+}
+
+/// A transaction started by `Backend::begin_transaction`. It's rolled back if dropped
+/// without being committed.
+#[async_trait]
+pub trait Transaction: Send {
+    /// Run an already rewritten statement in the transaction, like `Backend::run_query`
+    async fn run_query(
+        &mut self,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, QueryError>;
+
+    /// Run `sql` in the transaction as it is, without preparing it, like `Backend::execute_raw`
+    async fn execute_raw(&mut self, sql: &str) -> Result<()>;
+
+    /// Id of the transaction's connection on the database server, to find it in e.g.
+    /// `SHOW PROCESSLIST`
+    fn connection_id(&self) -> Option<u64>;
+
+    async fn commit(self: Box<Self>) -> Result<()>;
+
+    async fn rollback(self: Box<Self>) -> Result<()>;
+}
+
+/// Name of the database holding the snapshot of `database`, for the engines storing
+/// snapshots as databases
+fn snapshot_name(database: &str) -> String {
+    format!("{database}_rds_lite_snapshot")
+}
+
+/// Connections of a backend's pools
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub idle: usize,
+    pub active: usize,
+    /// Callers waiting for a connection to become available
+    pub waiting: usize,
+}
+
+impl PoolStats {
+    fn add_pool<DB: sqlx::Database>(&mut self, pool: &sqlx::Pool<DB>) {
+        let idle = pool.num_idle();
+        self.idle += idle;
+        self.active += (pool.size() as usize).saturating_sub(idle);
+    }
+}
+
+/// How long dropping or copying a database waits for its connections in use, e.g. by an open
+/// transaction, to be returned before going on without them
+const POOL_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Close a pool of `database`, waiting at most `POOL_CLOSE_TIMEOUT` for its connections in use
+async fn close_pool<DB: sqlx::Database>(pool: &sqlx::Pool<DB>, database: &str) {
+    if tokio::time::timeout(POOL_CLOSE_TIMEOUT, pool.close())
+        .await
+        .is_err()
+    {
+        warn!(
+            "Connections to database '{database}' are still in use after {}ms, e.g. by open transactions",
+            POOL_CLOSE_TIMEOUT.as_millis()
+        );
+    }
+}
+
+/// Counts the callers waiting for a pool connection, as sqlx doesn't expose it
+#[derive(Default)]
+struct Waiters(AtomicUsize);
+
+impl Waiters {
+    /// Await `acquire`, counting the caller as waiting until it completes or is cancelled
+    async fn wait<F: Future>(&self, acquire: F) -> F::Output {
+        struct Guard<'a>(&'a AtomicUsize);
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        self.0.fetch_add(1, Ordering::Relaxed);
+        let _guard = Guard(&self.0);
+        acquire.await
+    }
+
+    fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Options of the pools sized by `config`
+fn pool_options<DB: sqlx::Database>(config: &PoolConfig) -> PoolOptions<DB> {
+    let optional = |ms| (ms > 0).then(|| Duration::from_millis(ms));
+    PoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(Duration::from_millis(config.acquire_timeout_ms))
+        .idle_timeout(optional(config.idle_timeout_ms))
+        .max_lifetime(optional(config.max_lifetime_ms))
+}
+
+/// The error of a request that couldn't get a connection of `pool`. An exhausted pool is
+/// reported as a `ServiceUnavailableError`, which the AWS SDKs retry.
+fn acquire_error<DB: sqlx::Database>(pool: &sqlx::Pool<DB>, e: sqlx::Error) -> QueryError {
+    let options = pool.options();
+    let max_connections = options.get_max_connections();
+    match e {
+        sqlx::Error::PoolTimedOut if pool.size() >= max_connections => {
+            let error = AwsError::new(
+                "ServiceUnavailableError",
+                format!(
+                    "All {max_connections} connections of the pool are in use, none was released within {}ms",
+                    options.get_acquire_timeout().as_millis()
+                ),
+            );
+            warn!("{}", error.message);
+            (error.status, error.into())
+        }
+        e => {
+            error!("Failed to acquire a database connection: {e:?}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.into())
+        }
+    }
+}
+
+fn scheme(url: &str) -> Result<&str> {
+    match url.split_once(':') {
+        Some((scheme, _)) => Ok(scheme),
+        None => bail!("Database URL '{url}' has no scheme"),
+    }
+}
+
+/// Check that `url` is well formed and names a supported database engine, without connecting
+pub fn validate_url(url: &str) -> Result<()> {
+    match scheme(url)? {
+        "mysql" | "mariadb" => drop(MySqlConnectOptions::from_str(url)?),
+        "postgres" | "postgresql" => drop(PgConnectOptions::from_str(url)?),
+        "sqlite" => drop(SqliteBackend::connect_lazy(url)?),
+        "mock" => drop(MockBackend::connect_lazy(url)?),
+        scheme => bail!("Unsupported database URL scheme '{scheme}'"),
+    }
+    Ok(())
+}
+
+/// Create a backend for `url`, picking the database engine from its scheme, with pools
+/// sized by `pool`. No connection is opened until the first statement is run.
+pub fn connect_lazy(url: &str, pool: &PoolConfig) -> Result<Arc<dyn Backend>> {
+    let backend: Arc<dyn Backend> = match scheme(url)? {
+        "mysql" | "mariadb" => Arc::new(MySqlBackend::connect_lazy_with_pool(url, pool)?),
+        "postgres" | "postgresql" => Arc::new(PostgresBackend::connect_lazy_with_pool(url, pool)?),
+        "sqlite" => Arc::new(SqliteBackend::connect_lazy_with_pool(url, pool)?),
+        "mock" => Arc::new(MockBackend::connect_lazy(url)?),
+        scheme => bail!("Unsupported database URL scheme '{scheme}'"),
+    };
+    Ok(backend)
+}
@@ -0,0 +1,436 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use log::{debug, warn};
+use regex::Regex;
+use serde::Deserialize;
+use sqlx::Either;
+
+use crate::aws::{ColumnMetadataDef, FieldDef, SqlParameterDef};
+use crate::backend::{Backend, PoolStats, Transaction};
+use crate::error::AwsError;
+use crate::query::{Dialect, QueryError, QueryOutput, Records, ordered_parameters};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+}
+
+/// Answers to the statements matching all of the conditions
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    /// Regular expression searched in the SQL, with its `:name` parameters as written
+    sql: Option<String>,
+    database: Option<String>,
+    /// Parameters the statement must have, with these values
+    #[serde(default)]
+    parameters: BTreeMap<String, FieldDef>,
+    /// Answered in order, one per matching statement. The last one keeps being answered.
+    responses: Vec<MockResponse>,
+}
+
+/// The outcome of a statement: records, a count of updated records or an AWS error
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MockResponse {
+    records: Option<Vec<Vec<FieldDef>>>,
+    column_metadata: Option<Vec<ColumnMetadataDef>>,
+    number_of_records_updated: Option<u64>,
+    /// e.g. `StatementTimeoutException`
+    error: Option<String>,
+    message: Option<String>,
+    /// Defaults to the status AWS uses for `error`
+    status: Option<u16>,
+}
+
+impl MockResponse {
+    fn validate(&self) -> Result<()> {
+        let outcomes = [
+            self.records.is_some(),
+            self.number_of_records_updated.is_some(),
+            self.error.is_some(),
+        ];
+        if outcomes.into_iter().filter(|set| *set).count() != 1 {
+            bail!("A response needs one of records, number_of_records_updated or error");
+        }
+        if self.column_metadata.is_some() && self.records.is_none() {
+            bail!("column_metadata is only answered with records");
+        }
+        if (self.message.is_some() || self.status.is_some()) && self.error.is_none() {
+            bail!("message and status are only answered with an error");
+        }
+        if let Some(status) = self.status {
+            StatusCode::from_u16(status).with_context(|| format!("Invalid status {status}"))?;
+        }
+        Ok(())
+    }
+}
+
+struct Rule {
+    sql: Option<Regex>,
+    database: Option<String>,
+    parameters: BTreeMap<String, FieldDef>,
+    responses: Vec<MockResponse>,
+    /// Statements the rule matched so far
+    calls: AtomicUsize,
+}
+
+impl Rule {
+    fn matches(&self, database: Option<&str>, sql: &str, params: &[SqlParameterDef]) -> bool {
+        self.sql.as_ref().is_none_or(|regex| regex.is_match(sql))
+            && self
+                .database
+                .as_deref()
+                .is_none_or(|expected| database == Some(expected))
+            && self.parameters.iter().all(|(name, value)| {
+                params
+                    .iter()
+                    .any(|param| param.name == *name && param.value == *value)
+            })
+    }
+
+    /// The response to the statement the rule just matched
+    fn next_response(&self) -> &MockResponse {
+        let call = self.calls.fetch_add(1, Ordering::Relaxed);
+        &self.responses[call.min(self.responses.len() - 1)]
+    }
+}
+
+/// The rules of a mock backend, shared with its transactions
+struct Rules(Vec<Rule>);
+
+impl Rules {
+    /// Answer `sql` once for each set of parameters in `params`, from the first rule each of
+    /// them matches
+    fn answer(
+        &self,
+        database: Option<&str>,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: &[Vec<SqlParameterDef>],
+    ) -> Result<QueryOutput, QueryError> {
+        let mut records: Option<Records> = None;
+        let mut updated = 0;
+        for row_params in params {
+            ordered_parameters(args_to_be_bound, row_params)
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+            let Some((index, rule)) = self
+                .0
+                .iter()
+                .enumerate()
+                .find(|(_, rule)| rule.matches(database, sql, row_params))
+            else {
+                warn!("No mock rule matches '{sql}'");
+                let error = AwsError::new(
+                    "BadRequestException",
+                    format!("No mock rule matches the statement: {sql}"),
+                );
+                return Err((error.status, error.into()));
+            };
+            debug!("Mock rule {} matches '{sql}'", index + 1);
+
+            let response = rule.next_response();
+            if let Some(error) = &response.error {
+                let mut aws_error = AwsError::new(
+                    error,
+                    response
+                        .message
+                        .clone()
+                        .unwrap_or_else(|| format!("{error} answered by a mock rule")),
+                );
+                if let Some(status) = response.status.and_then(|s| StatusCode::from_u16(s).ok()) {
+                    aws_error.status = status;
+                }
+                return Err((aws_error.status, aws_error.into()));
+            }
+            if let Some(rows) = &response.records {
+                let records = records.get_or_insert_with(Records::default);
+                records.rows.extend(rows.iter().cloned());
+                if records.column_metadata.is_none() {
+                    records.column_metadata = response.column_metadata.clone();
+                }
+            }
+            updated += response.number_of_records_updated.unwrap_or_default();
+        }
+
+        Ok(match records {
+            Some(records) => Either::Left(records),
+            None => Either::Right(updated),
+        })
+    }
+
+    /// Start the sequences of responses of the rules applying to `database` over
+    fn rewind(&self, database: &str) {
+        for rule in &self.0 {
+            if rule.database.as_deref().is_none_or(|d| d == database) {
+                rule.calls.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Answers statements from scripted rules instead of running them, for the unit tests of
+/// data access code. No database is involved.
+pub struct MockBackend {
+    rules: Arc<Rules>,
+}
+
+impl MockBackend {
+    /// Accepts `mock://<path of the rules file>`
+    pub fn connect_lazy(url: &str) -> Result<Self> {
+        let path = url.trim_start_matches("mock:").trim_start_matches("//");
+        if path.is_empty() {
+            bail!("Mock URL must be mock://<path of the rules file>");
+        }
+        Self::load(Path::new(path))
+    }
+
+    /// Read the TOML rules file at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read mock rules {}", path.display()))?;
+        Self::from_toml(&contents).with_context(|| format!("Invalid mock rules {}", path.display()))
+    }
+
+    /// Parse rules written like a rules file
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let file: RulesFile = toml::from_str(contents)?;
+        let mut rules = vec![];
+        for (index, config) in file.rules.into_iter().enumerate() {
+            let rule =
+                Rule::try_from(config).with_context(|| format!("Invalid rule {}", index + 1))?;
+            rules.push(rule);
+        }
+        Ok(Self {
+            rules: Arc::new(Rules(rules)),
+        })
+    }
+}
+
+impl TryFrom<RuleConfig> for Rule {
+    type Error = anyhow::Error;
+
+    fn try_from(config: RuleConfig) -> Result<Self> {
+        let sql = match &config.sql {
+            Some(sql) => {
+                Some(Regex::new(sql).with_context(|| format!("Invalid sql regex '{sql}'"))?)
+            }
+            None => None,
+        };
+        if config.responses.is_empty() {
+            bail!("No responses");
+        }
+        for (index, response) in config.responses.iter().enumerate() {
+            response
+                .validate()
+                .with_context(|| format!("Invalid response {}", index + 1))?;
+        }
+        Ok(Self {
+            sql,
+            database: config.database,
+            parameters: config.parameters,
+            responses: config.responses,
+            calls: AtomicUsize::new(0),
+        })
+    }
+}
+
+/// A transaction of the mock backend, answering from the same rules. Committing and
+/// rolling back always succeed.
+struct MockTransaction {
+    rules: Arc<Rules>,
+    database: Option<String>,
+}
+
+#[async_trait]
+impl Transaction for MockTransaction {
+    async fn run_query(
+        &mut self,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, QueryError> {
+        self.rules
+            .answer(self.database.as_deref(), sql, args_to_be_bound, &params)
+    }
+
+    async fn execute_raw(&mut self, _sql: &str) -> Result<()> {
+        Err(anyhow!("The mock backend can't run SQL scripts"))
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        None
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for MockBackend {
+    fn dialect(&self) -> Dialect {
+        Dialect::Mock
+    }
+
+    async fn run_query(
+        &self,
+        database: Option<&str>,
+        _schema: Option<&str>,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, QueryError> {
+        self.rules.answer(database, sql, args_to_be_bound, &params)
+    }
+
+    async fn begin_transaction(
+        &self,
+        database: Option<&str>,
+        _schema: Option<&str>,
+    ) -> Result<Box<dyn Transaction>, QueryError> {
+        Ok(Box::new(MockTransaction {
+            rules: self.rules.clone(),
+            database: database.map(str::to_string),
+        }))
+    }
+
+    async fn execute_raw(&self, _database: &str, _sql: &str) -> Result<()> {
+        Err(anyhow!("The mock backend can't run SQL scripts"))
+    }
+
+    async fn create_database(&self, _database: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn clone_database(&self, _template: &str, _database: &str) -> Result<()> {
+        Err(anyhow!("The mock backend can't clone databases"))
+    }
+
+    async fn drop_database(&self, _database: &str) -> Result<()> {
+        Err(anyhow!("The mock backend can't drop databases"))
+    }
+
+    async fn snapshot_database(&self, _database: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Rewind the responses of the rules applying to `database`
+    async fn restore_database(&self, database: &str) -> Result<()> {
+        self.rules.rewind(database);
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        PoolStats::default()
+    }
+
+    async fn close(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    use crate::Builder;
+    use crate::test_util::call;
+
+    const RULES: &str = r#"
+        [[rules]]
+        sql = "(?i)^select name from users where id = :id$"
+        parameters = { id = { longValue = 1 } }
+
+        [[rules.responses]]
+        records = [[{ stringValue = "alice" }]]
+        column_metadata = [{ name = "name", typeName = "VARCHAR" }]
+
+        [[rules.responses]]
+        error = "StatementTimeoutException"
+
+        [[rules]]
+        sql = "(?i)^update users"
+        responses = [{ number_of_records_updated = 2 }]
+    "#;
+
+    #[tokio::test]
+    async fn test_mock_rules() {
+        let app = Builder::new()
+            .backend(MockBackend::from_toml(RULES).unwrap())
+            .router()
+            .unwrap();
+        let select = |id: i64| {
+            json!({
+                "sql": "SELECT name FROM users WHERE id = :id",
+                "parameters": [{"name": "id", "value": {"longValue": id}}],
+                "includeResultMetadata": true,
+            })
+        };
+
+        let (status, body) = call(&app, "POST", "/Execute", select(1)).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["records"], json!([[{"stringValue": "alice"}]]));
+        assert_eq!(body["columnMetadata"][0]["typeName"], "VARCHAR");
+        // The second call gets the second response, and so do the following ones
+        for _ in 0..2 {
+            let (status, body) = call(&app, "POST", "/Execute", select(1)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["type"], "StatementTimeoutException");
+        }
+
+        let (status, body) = call(&app, "POST", "/Execute", select(2)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["type"], "BadRequestException");
+        assert!(body["message"].as_str().unwrap().contains("No mock rule"));
+
+        let (_, body) = call(&app, "POST", "/BeginTransaction", json!({})).await;
+        let update = json!({
+            "sql": "UPDATE users SET name = 'bob'",
+            "transactionId": body["transactionId"],
+        });
+        let (status, body) = call(&app, "POST", "/Execute", update).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["numberOfRecordsUpdated"], 2);
+
+        // Resetting the database starts the sequences over
+        let (status, _) = call(&app, "POST", "/_admin/databases/app/reset", Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = call(&app, "POST", "/Execute", select(1)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let err = MockBackend::from_toml("[[rules]]\nresponses = []")
+            .err()
+            .unwrap();
+        assert!(format!("{err:#}").contains("No responses"));
+
+        let both = r#"[[rules]]
+            responses = [{ number_of_records_updated = 1, error = "BadRequestException" }]"#;
+        let err = MockBackend::from_toml(both).err().unwrap();
+        assert!(format!("{err:#}").contains("needs one of"));
+
+        let err = MockBackend::connect_lazy("mock:///nonexistent/rules.toml")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Failed to read mock rules"));
+    }
+}
@@ -0,0 +1,479 @@
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use sqlx::pool::PoolConnection;
+use sqlx::{Column, ConnectOptions, Connection, Row, TypeInfo};
+use sqlx::{
+    Either, Executor, MySql, MySqlPool,
+    mysql::{MySqlArguments, MySqlColumn, MySqlConnectOptions, MySqlConnection, MySqlRow},
+    query::Query,
+};
+
+use crate::aws::{FieldDef, SqlParameterDef};
+use crate::backend::{
+    Backend, PoolStats, Transaction, Waiters, acquire_error, pool_options, snapshot_name,
+};
+use crate::config::PoolConfig;
+use crate::query::{Dialect, QueryError, QueryOutput, is_select, ordered_parameters};
+
+pub struct MySqlBackend {
+    pool: MySqlPool,
+    waiters: Waiters,
+}
+
+impl MySqlBackend {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self {
+            pool,
+            waiters: Waiters::default(),
+        }
+    }
+
+    pub fn connect_lazy(url: &str) -> Result<Self, sqlx::Error> {
+        Self::connect_lazy_with_pool(url, &PoolConfig::default())
+    }
+
+    /// Like `connect_lazy`, with a pool sized by `pool`
+    pub fn connect_lazy_with_pool(url: &str, pool: &PoolConfig) -> Result<Self, sqlx::Error> {
+        let options = url
+            .parse::<MySqlConnectOptions>()?
+            .statement_cache_capacity(pool.statement_cache_capacity);
+        Ok(Self::new(pool_options(pool).connect_lazy_with(options)))
+    }
+
+    /// A connection of the pool, switched to `database`
+    async fn connection(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+    ) -> Result<PoolConnection<MySql>, QueryError> {
+        if schema.is_some() {
+            return Err((
+                StatusCode::NOT_IMPLEMENTED,
+                anyhow!("Schema selection is not supported"),
+            ));
+        }
+
+        // Statements must run on the connection the "USE database" command was run on
+        let mut conn = self
+            .waiters
+            .wait(self.pool.acquire())
+            .await
+            .map_err(|e| acquire_error(&self.pool, e))?;
+
+        if let Some(database) = database {
+            conn.execute(sqlx::raw_sql(&format!(
+                "USE {}",
+                quote_identifier(database)
+            )))
+            .await
+            .inspect_err(|e| error!("Failed to select database '{database}': {e:?}"))
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+        }
+        Ok(conn)
+    }
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("`{}`", identifier.replace('`', "``"))
+}
+
+async fn base_tables(
+    conn: &mut PoolConnection<MySql>,
+    database: &str,
+) -> anyhow::Result<Vec<String>> {
+    let rows = sqlx::query(
+        "SELECT table_name FROM information_schema.tables WHERE table_schema = ? AND table_type = 'BASE TABLE'",
+    )
+    .bind(database)
+    .fetch_all(&mut **conn)
+    .await?;
+    let tables = rows
+        .iter()
+        .map(|row| row.try_get(0))
+        .collect::<Result<_, _>>()?;
+    Ok(tables)
+}
+
+/// Whether the database `database` exists
+async fn schema_exists(conn: &mut PoolConnection<MySql>, database: &str) -> anyhow::Result<bool> {
+    let exists = sqlx::query("SELECT 1 FROM information_schema.schemata WHERE schema_name = ?")
+        .bind(database.to_string())
+        .fetch_optional(&mut **conn)
+        .await?
+        .is_some();
+    Ok(exists)
+}
+
+/// Copy the tables of database `from`, with their rows, into database `to`
+async fn copy_tables(conn: &mut PoolConnection<MySql>, from: &str, to: &str) -> anyhow::Result<()> {
+    let tables = base_tables(conn, from).await?;
+    let (from, to) = (quote_identifier(from), quote_identifier(to));
+
+    // Tables are created in `to` from their definition, which keeps their foreign keys
+    let statements = [
+        "SET FOREIGN_KEY_CHECKS = 0".to_string(),
+        format!("USE {to}"),
+    ];
+    for sql in statements {
+        conn.execute(sqlx::raw_sql(&sql)).await?;
+    }
+    for table in tables {
+        let table = quote_identifier(&table);
+        let show = format!("SHOW CREATE TABLE {from}.{table}");
+        let definition: String = sqlx::query(&show)
+            .fetch_one(&mut **conn)
+            .await?
+            .try_get(1)?;
+        let copy = format!("INSERT INTO {to}.{table} SELECT * FROM {from}.{table}");
+        for sql in [definition, copy] {
+            conn.execute(sqlx::raw_sql(&sql)).await?;
+        }
+    }
+    conn.execute(sqlx::raw_sql("SET FOREIGN_KEY_CHECKS = 1"))
+        .await?;
+    Ok(())
+}
+
+/// Run `sql` once for each set of parameters in `params`
+async fn run_statements(
+    conn: &mut MySqlConnection,
+    sql: &str,
+    args_to_be_bound: &[String],
+    params: &[Vec<SqlParameterDef>],
+) -> Result<QueryOutput, QueryError> {
+    let value = if is_select(sql) {
+        let mut collected_records = vec![];
+
+        for row_params in params {
+            let query = ordered_parameters(args_to_be_bound, row_params)
+                .and_then(|ordered| bind_parameters(sqlx::query(sql), &ordered))
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+            let records = query
+                .fetch_all(&mut *conn)
+                .await
+                .inspect_err(|e| error!("Failed to execute query: {e:?}"))
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+
+            collected_records.extend(
+                records
+                    .into_iter()
+                    .filter_map(|row| try_row_to_aws_fields(row).ok()),
+            );
+        }
+
+        Either::Left(collected_records.into())
+    } else {
+        let mut affected_rows = 0;
+        for row_params in params {
+            let query = ordered_parameters(args_to_be_bound, row_params)
+                .and_then(|ordered| bind_parameters(sqlx::query(sql), &ordered))
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+            affected_rows += query
+                .execute(&mut *conn)
+                .await
+                .inspect_err(|e| error!("Failed to execute query: {e:?}"))
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?
+                .rows_affected();
+        }
+
+        Either::Right(affected_rows)
+    };
+
+    Ok(value)
+}
+
+/// A Data API transaction, holding its connection until it ends
+struct MySqlTransaction {
+    transaction: sqlx::Transaction<'static, MySql>,
+    connection_id: u64,
+}
+
+#[async_trait]
+impl Transaction for MySqlTransaction {
+    async fn run_query(
+        &mut self,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, QueryError> {
+        run_statements(&mut self.transaction, sql, args_to_be_bound, &params).await
+    }
+
+    async fn execute_raw(&mut self, sql: &str) -> anyhow::Result<()> {
+        self.transaction.execute(sqlx::raw_sql(sql)).await?;
+        Ok(())
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        Some(self.connection_id)
+    }
+
+    async fn commit(self: Box<Self>) -> anyhow::Result<()> {
+        self.transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()> {
+        self.transaction.rollback().await?;
+        Ok(())
+    }
+}
+
+pub fn try_row_to_aws_fields(row: MySqlRow) -> Result<Vec<FieldDef>, sqlx::Error> {
+    let columns = row.columns();
+    let mut values = Vec::new();
+
+    for column in columns {
+        let field = column_into_fielddef(&row, column).inspect_err(|e| {
+            error!(
+                "Error converting column '{}' to FieldDef: {e}",
+                column.name()
+            );
+        })?;
+
+        values.push(field);
+    }
+
+    Ok(values)
+}
+
+fn column_into_fielddef(row: &MySqlRow, column: &MySqlColumn) -> Result<FieldDef, sqlx::Error> {
+    let column_name = column.name();
+    let type_name = column.type_info().name();
+
+    let field = match type_name {
+        "VARCHAR" | "CHAR" | "TEXT" | "LONGTEXT" | "MEDIUMTEXT" | "TINYTEXT" => {
+            match row.try_get::<Option<String>, _>(column_name)? {
+                Some(value) => FieldDef::StringValue(value),
+                None => FieldDef::IsNull(true),
+            }
+        }
+        "BOOLEAN" | "BOOL" => match row.try_get::<Option<bool>, _>(column_name)? {
+            Some(value) => FieldDef::BooleanValue(value),
+            None => FieldDef::IsNull(true),
+        },
+        "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "BIGINT" => {
+            match row.try_get::<Option<i64>, _>(column_name)? {
+                Some(value) => FieldDef::LongValue(value),
+                None => FieldDef::IsNull(true),
+            }
+        }
+        "FLOAT" | "DOUBLE" | "DECIMAL" | "NUMERIC" => {
+            match row.try_get::<Option<f64>, _>(column_name)? {
+                Some(value) => FieldDef::DoubleValue(value),
+                None => FieldDef::IsNull(true),
+            }
+        }
+        "DATE" | "DATETIME" | "TIMESTAMP" | "TIME" | "YEAR" => {
+            // Convert temporal types to string representation
+            match row.try_get::<Option<DateTime<Utc>>, _>(column_name)? {
+                Some(value) => FieldDef::StringValue(value.to_string()),
+                None => FieldDef::IsNull(true),
+            }
+        }
+        "VARBINARY" | "BINARY" | "BLOB" | "LONGBLOB" | "MEDIUMBLOB" | "TINYBLOB" => {
+            match row.try_get::<Option<Vec<u8>>, _>(column_name)? {
+                Some(value) => {
+                    // Encode to base64 string
+                    let value = base64::engine::general_purpose::STANDARD.encode(&value);
+                    FieldDef::BlobValue(value)
+                }
+                None => FieldDef::IsNull(true),
+            }
+        }
+        _ => {
+            info!("Unknown field type for column '{column_name}': {type_name}");
+            // Try to get as string for unknown types
+            match row.try_get::<Option<String>, _>(column_name)? {
+                Some(value) => FieldDef::StringValue(value),
+                None => FieldDef::IsNull(true),
+            }
+        }
+    };
+    Ok(field)
+}
+
+fn bind_parameters<'q>(
+    mut query: Query<'q, MySql, MySqlArguments>,
+    params: &[&'q SqlParameterDef],
+) -> Result<Query<'q, MySql, MySqlArguments>, anyhow::Error> {
+    for arg in params {
+        query = match &arg.value {
+            FieldDef::ArrayValue(_) => {
+                return Err(anyhow!("Array parameters are not supported"));
+            }
+            FieldDef::BlobValue(b64) => {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(b64)
+                    .map_err(|e| {
+                        anyhow!(
+                            "Failed to decode base64 blob for parameter '{}': {e}",
+                            arg.name
+                        )
+                    })?;
+                query.bind(data)
+            }
+            FieldDef::BooleanValue(x) => query.bind(*x),
+            FieldDef::DoubleValue(x) => query.bind(*x),
+            FieldDef::IsNull(_) => query.bind(None::<String>),
+            FieldDef::LongValue(x) => query.bind(*x),
+            FieldDef::StringValue(x) => query.bind(x.as_str()),
+        }
+    }
+
+    Ok(query)
+}
+
+#[async_trait]
+impl Backend for MySqlBackend {
+    fn dialect(&self) -> Dialect {
+        Dialect::MySql
+    }
+
+    async fn run_query(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, QueryError> {
+        let mut conn = self.connection(database, schema).await?;
+        run_statements(&mut conn, sql, args_to_be_bound, &params).await
+    }
+
+    async fn begin_transaction(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+    ) -> Result<Box<dyn Transaction>, QueryError> {
+        let mut conn = self.connection(database, schema).await?;
+        let connection_id: u64 = sqlx::query("SELECT CONNECTION_ID()")
+            .fetch_one(&mut *conn)
+            .await
+            .and_then(|row| row.try_get(0))
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+        let transaction = sqlx::Transaction::begin(conn, None)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+        Ok(Box::new(MySqlTransaction {
+            transaction,
+            connection_id,
+        }))
+    }
+
+    async fn execute_raw(&self, database: &str, sql: &str) -> anyhow::Result<()> {
+        let mut conn = self.waiters.wait(self.pool.acquire()).await?;
+        conn.execute(sqlx::raw_sql(&format!(
+            "USE {}",
+            quote_identifier(database)
+        )))
+        .await?;
+        conn.execute(sqlx::raw_sql(sql)).await?;
+        Ok(())
+    }
+
+    async fn create_database(&self, database: &str) -> anyhow::Result<()> {
+        let sql = format!(
+            "CREATE DATABASE IF NOT EXISTS {}",
+            quote_identifier(database)
+        );
+        sqlx::raw_sql(&sql).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn clone_database(&self, template: &str, database: &str) -> anyhow::Result<()> {
+        let mut conn = self.waiters.wait(self.pool.acquire()).await?;
+        // The connection's settings are changed along the way
+        conn.close_on_drop();
+
+        if !schema_exists(&mut conn, template).await? {
+            bail!("Unknown database '{template}'");
+        }
+        let statements = [
+            format!("DROP DATABASE IF EXISTS {}", quote_identifier(database)),
+            format!("CREATE DATABASE {}", quote_identifier(database)),
+        ];
+        for sql in statements {
+            conn.execute(sqlx::raw_sql(&sql)).await?;
+        }
+        copy_tables(&mut conn, template, database).await
+    }
+
+    async fn drop_database(&self, database: &str) -> anyhow::Result<()> {
+        let sql = format!("DROP DATABASE IF EXISTS {}", quote_identifier(database));
+        let mut conn = self.waiters.wait(self.pool.acquire()).await?;
+        conn.execute(sqlx::raw_sql(&sql)).await?;
+        Ok(())
+    }
+
+    async fn snapshot_database(&self, database: &str) -> anyhow::Result<()> {
+        self.clone_database(database, &snapshot_name(database))
+            .await
+    }
+
+    async fn restore_database(&self, database: &str) -> anyhow::Result<()> {
+        let snapshot = snapshot_name(database);
+        let mut conn = self.waiters.wait(self.pool.acquire()).await?;
+        conn.close_on_drop();
+
+        if !schema_exists(&mut conn, &snapshot).await? {
+            bail!("No snapshot of database '{database}'");
+        }
+
+        let mut statements = vec!["SET FOREIGN_KEY_CHECKS = 0".to_string()];
+        for table in base_tables(&mut conn, database).await? {
+            statements.push(format!(
+                "DROP TABLE {}.{}",
+                quote_identifier(database),
+                quote_identifier(&table)
+            ));
+        }
+        for sql in statements {
+            conn.execute(sqlx::raw_sql(&sql)).await?;
+        }
+        copy_tables(&mut conn, &snapshot, database).await
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn check_connection(&self) -> anyhow::Result<()> {
+        let connection = self.pool.connect_options().connect().await?;
+        connection.close().await?;
+        Ok(())
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        let mut stats = PoolStats {
+            waiting: self.waiters.count(),
+            ..PoolStats::default()
+        };
+        stats.add_pool(&self.pool);
+        stats
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("app"), "`app`");
+        assert_eq!(quote_identifier("my app; DROP"), "`my app; DROP`");
+        assert_eq!(quote_identifier("a`b"), "`a``b`");
+    }
+}
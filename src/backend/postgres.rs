@@ -0,0 +1,856 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use base64::Engine as _;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use hashlink::LruCache;
+use log::{debug, error, info};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::{
+    PgArgumentBuffer, PgArguments, PgColumn, PgConnectOptions, PgConnection, PgDatabaseError,
+    PgErrorPosition, PgPool, PgRow, PgTypeInfo, types::Oid,
+};
+use sqlx::query::Query;
+use sqlx::types::Json;
+use sqlx::{Column, Decode, Encode, Row, Type, TypeInfo};
+use sqlx::{ConnectOptions, Connection, Either, Executor, Postgres};
+use uuid::Uuid;
+
+use crate::aws::{ArrayValueDef, FieldDef, SqlParameterDef, TypeHintDef};
+use crate::backend::{
+    Backend, PoolStats, Transaction, Waiters, acquire_error, close_pool, pool_options,
+    snapshot_name,
+};
+use crate::config::PoolConfig;
+use crate::query::{Dialect, QueryError, QueryOutput, has_keyword, is_select, ordered_parameters};
+
+/// Pools a backend keeps open at once. Opening one for another database closes the least
+/// recently used one, so that requests cycling through database names don't open
+/// `max_connections` connections to each of them.
+const MAX_POOLS: usize = 16;
+
+/// Runs statements on PostgreSQL, emulating Aurora PostgreSQL clusters.
+/// Unlike MySQL, a PostgreSQL connection is bound to a single database, so a pool
+/// is opened lazily for every database that is requested.
+pub struct PostgresBackend {
+    options: PgConnectOptions,
+    pool_config: PoolConfig,
+    pools: Mutex<LruCache<Option<String>, PgPool>>,
+    waiters: Waiters,
+}
+
+impl PostgresBackend {
+    pub fn connect_lazy(url: &str) -> Result<Self, sqlx::Error> {
+        Self::connect_lazy_with_pool(url, &PoolConfig::default())
+    }
+
+    /// Like `connect_lazy`, with pools sized by `pool`
+    pub fn connect_lazy_with_pool(url: &str, pool: &PoolConfig) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            options: PgConnectOptions::from_str(url)?
+                .statement_cache_capacity(pool.statement_cache_capacity),
+            pool_config: *pool,
+            pools: Mutex::new(LruCache::new(MAX_POOLS)),
+            waiters: Waiters::default(),
+        })
+    }
+
+    /// Pool for `database`, or for the database named in the URL if `None`
+    fn pool(&self, database: Option<&str>) -> PgPool {
+        let key = database.map(str::to_string);
+        let mut pools = self.pools.lock().unwrap();
+        if let Some(pool) = pools.get(&key) {
+            return pool.clone();
+        }
+
+        if pools.len() >= MAX_POOLS
+            && let Some((evicted, pool)) = pools.remove_lru()
+        {
+            debug!(
+                "Closing the pool of database '{}' to open another one",
+                evicted.as_deref().unwrap_or_default()
+            );
+            // Connections held by transactions stay usable, and are closed once returned
+            tokio::spawn(async move { pool.close().await });
+        }
+        let options = match database {
+            Some(database) => self.options.clone().database(database),
+            None => self.options.clone(),
+        };
+        let pool = pool_options(&self.pool_config).connect_lazy_with(options);
+        pools.insert(key, pool.clone());
+        pool
+    }
+
+    /// A connection of the pool of `database`, using `schema`
+    async fn connection(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+    ) -> Result<PoolConnection<Postgres>, QueryError> {
+        let pool = self.pool(database);
+        let mut conn = self
+            .waiters
+            .wait(pool.acquire())
+            .await
+            .map_err(|e| acquire_error(&pool, e))?;
+
+        // Connections are shared between requests, so the search path is always set
+        // to avoid inheriting the one chosen by a previous request
+        let search_path = match schema {
+            Some(schema) => format!("SET search_path TO {}", quote_identifier(schema)),
+            None => "RESET search_path".to_string(),
+        };
+        conn.execute(sqlx::raw_sql(&search_path))
+            .await
+            .inspect_err(|e| error!("Failed to select schema '{schema:?}': {e:?}"))
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, describe_error(e)))?;
+        Ok(conn)
+    }
+
+    /// Close the pools connected to `database`, as PostgreSQL can't use a database with open
+    /// connections as a template, nor drop it. Connections still held by transactions after
+    /// a while are left for `DROP DATABASE ... WITH (FORCE)` to terminate, or make the copy fail.
+    async fn close_pools(&self, database: &str) {
+        let pools: Vec<PgPool> = {
+            let mut pools = self.pools.lock().unwrap();
+            let mut closed: Vec<PgPool> = pools
+                .remove(&Some(database.to_string()))
+                .into_iter()
+                .collect();
+            if self.options.get_database() == Some(database) {
+                closed.extend(pools.remove(&None));
+            }
+            closed
+        };
+        for pool in pools {
+            close_pool(&pool, database).await;
+        }
+    }
+
+    /// A connection to the `postgres` maintenance database, to create and drop databases
+    /// while no one is connected to them
+    async fn maintenance_connection(&self) -> Result<PgConnection, anyhow::Error> {
+        PgConnection::connect_with(&self.options.clone().database("postgres"))
+            .await
+            .map_err(describe_error)
+    }
+
+    async fn database_exists(
+        conn: &mut PgConnection,
+        database: &str,
+    ) -> Result<bool, anyhow::Error> {
+        let exists = sqlx::query("SELECT 1 FROM pg_database WHERE datname = $1")
+            .bind(database)
+            .fetch_optional(conn)
+            .await
+            .map_err(describe_error)?
+            .is_some();
+        Ok(exists)
+    }
+}
+
+/// A NULL parameter whose type is inferred by the server from the statement, so that
+/// `isNull` can be bound to a column of any type
+struct UntypedNull;
+
+impl Type<Postgres> for UntypedNull {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_oid(Oid(0))
+    }
+}
+
+impl Encode<'_, Postgres> for UntypedNull {
+    fn encode_by_ref(&self, _buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        Ok(IsNull::Yes)
+    }
+}
+
+/// Format errors the way the Data API reports them for Aurora PostgreSQL, e.g.
+/// `ERROR: relation "foo" does not exist\n  Position: 15; SQLState: 42P01`
+fn describe_error(e: sqlx::Error) -> anyhow::Error {
+    let Some(pg) = e
+        .as_database_error()
+        .and_then(|e| e.try_downcast_ref::<PgDatabaseError>())
+    else {
+        return e.into();
+    };
+
+    let mut message = format!("ERROR: {}", pg.message());
+    if let Some(PgErrorPosition::Original(position)) = pg.position() {
+        message.push_str(&format!("\n  Position: {position}"));
+    }
+    anyhow!("{message}; SQLState: {}", pg.code())
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn try_row_to_aws_fields(row: PgRow) -> Result<Vec<FieldDef>, sqlx::Error> {
+    let columns = row.columns();
+    let mut values = Vec::new();
+
+    for column in columns {
+        let field = column_into_fielddef(&row, column).inspect_err(|e| {
+            error!(
+                "Error converting column '{}' to FieldDef: {e}",
+                column.name()
+            );
+        })?;
+
+        values.push(field);
+    }
+
+    Ok(values)
+}
+
+/// Decode column `index` as a `T`, mapping NULL to `isNull`
+fn get<'r, T>(
+    row: &'r PgRow,
+    index: usize,
+    into_field: impl FnOnce(T) -> FieldDef,
+) -> Result<FieldDef, sqlx::Error>
+where
+    T: Decode<'r, Postgres> + Type<Postgres>,
+{
+    match row.try_get::<Option<T>, _>(index)? {
+        Some(value) => Ok(into_field(value)),
+        None => Ok(FieldDef::IsNull(true)),
+    }
+}
+
+/// Number of decimal digits a NUMERIC value is displayed with, e.g. 2 for `12.50`.
+/// sqlx ignores it when decoding a `BigDecimal`, so it's read from the wire format header.
+fn numeric_display_scale(bytes: &[u8]) -> Result<i64, sqlx::Error> {
+    // The header is the number of digits, the weight, the sign then the display scale
+    match bytes.get(6..8) {
+        Some(&[hi, lo]) => Ok(u16::from_be_bytes([hi, lo]).into()),
+        _ => Err(sqlx::Error::Decode("NUMERIC value is too short".into())),
+    }
+}
+
+/// Display scales of the elements of a one-dimensional NUMERIC array, read from its wire
+/// format: the number of dimensions, a flag, the element type and the size and lower bound of
+/// each dimension, then each element after its length
+fn numeric_array_display_scales(bytes: &[u8]) -> Result<Vec<i64>, sqlx::Error> {
+    let too_short = || sqlx::Error::Decode("NUMERIC array is too short".into());
+    let read_i32 = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(too_short)
+    };
+
+    let dimensions = read_i32(0)?;
+    if dimensions == 0 {
+        return Ok(vec![]);
+    }
+    if dimensions != 1 {
+        return Err(sqlx::Error::Decode(
+            "Multidimensional NUMERIC arrays are not supported".into(),
+        ));
+    }
+    let len = read_i32(12)?;
+    let mut at = 20;
+    let mut scales = vec![];
+    for _ in 0..len {
+        let element_len = usize::try_from(read_i32(at)?)
+            .map_err(|_| sqlx::Error::Decode("NULL in NUMERIC array".into()))?;
+        at += 4;
+        let element = bytes.get(at..at + element_len).ok_or_else(too_short)?;
+        scales.push(numeric_display_scale(element)?);
+        at += element_len;
+    }
+    Ok(scales)
+}
+
+fn column_into_fielddef(row: &PgRow, column: &PgColumn) -> Result<FieldDef, sqlx::Error> {
+    // Columns are looked up by position, as PostgreSQL happily returns several
+    // columns with the same name (e.g. `SELECT 1, 2` gives two "?column?")
+    let index = column.ordinal();
+    let type_name = column.type_info().name();
+
+    let field = match type_name {
+        "BOOL" => get(row, index, FieldDef::BooleanValue)?,
+        "INT2" => get(row, index, |v: i16| FieldDef::LongValue(v.into()))?,
+        "INT4" => get(row, index, |v: i32| FieldDef::LongValue(v.into()))?,
+        "INT8" => get(row, index, FieldDef::LongValue)?,
+        "FLOAT4" => get(row, index, |v: f32| FieldDef::DoubleValue(v.into()))?,
+        "FLOAT8" => get(row, index, FieldDef::DoubleValue)?,
+        // NUMERIC is returned as a string so that no precision is lost
+        "NUMERIC" => match row.try_get::<Option<BigDecimal>, _>(index)? {
+            Some(value) => {
+                let bytes = row
+                    .try_get_raw(index)?
+                    .as_bytes()
+                    .map_err(sqlx::Error::Decode)?;
+                let scale = numeric_display_scale(bytes)?;
+                FieldDef::StringValue(value.with_scale(scale).to_plain_string())
+            }
+            None => FieldDef::IsNull(true),
+        },
+        "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" => get(row, index, FieldDef::StringValue)?,
+        "UUID" => get(row, index, |v: Uuid| FieldDef::StringValue(v.to_string()))?,
+        "JSON" | "JSONB" => get(row, index, |v: serde_json::Value| {
+            FieldDef::StringValue(v.to_string())
+        })?,
+        // Temporal types are returned as strings in the same format the Data API uses
+        "DATE" => get(row, index, |v: NaiveDate| {
+            FieldDef::StringValue(v.format("%Y-%m-%d").to_string())
+        })?,
+        "TIME" => get(row, index, |v: NaiveTime| {
+            FieldDef::StringValue(v.format("%H:%M:%S%.f").to_string())
+        })?,
+        "TIMESTAMP" => get(row, index, |v: NaiveDateTime| {
+            FieldDef::StringValue(v.format("%Y-%m-%d %H:%M:%S%.f").to_string())
+        })?,
+        "TIMESTAMPTZ" => get(row, index, |v: DateTime<Utc>| {
+            FieldDef::StringValue(v.format("%Y-%m-%d %H:%M:%S%.f").to_string())
+        })?,
+        "BYTEA" => get(row, index, |v: Vec<u8>| {
+            FieldDef::BlobValue(base64::engine::general_purpose::STANDARD.encode(&v))
+        })?,
+        "BOOL[]" => get(row, index, |v: Vec<bool>| {
+            FieldDef::ArrayValue(ArrayValueDef::BooleanValues(v))
+        })?,
+        "INT2[]" => get(row, index, |v: Vec<i16>| {
+            FieldDef::ArrayValue(ArrayValueDef::LongValues(
+                v.into_iter().map(i64::from).collect(),
+            ))
+        })?,
+        "INT4[]" => get(row, index, |v: Vec<i32>| {
+            FieldDef::ArrayValue(ArrayValueDef::LongValues(
+                v.into_iter().map(i64::from).collect(),
+            ))
+        })?,
+        "INT8[]" => get(row, index, |v: Vec<i64>| {
+            FieldDef::ArrayValue(ArrayValueDef::LongValues(v))
+        })?,
+        "FLOAT4[]" => get(row, index, |v: Vec<f32>| {
+            FieldDef::ArrayValue(ArrayValueDef::DoubleValues(
+                v.into_iter().map(f64::from).collect(),
+            ))
+        })?,
+        "FLOAT8[]" => get(row, index, |v: Vec<f64>| {
+            FieldDef::ArrayValue(ArrayValueDef::DoubleValues(v))
+        })?,
+        "TEXT[]" | "VARCHAR[]" | "BPCHAR[]" | "NAME[]" => get(row, index, |v: Vec<String>| {
+            FieldDef::ArrayValue(ArrayValueDef::StringValues(v))
+        })?,
+        "NUMERIC[]" => match row.try_get::<Option<Vec<BigDecimal>>, _>(index)? {
+            Some(values) => {
+                let bytes = row
+                    .try_get_raw(index)?
+                    .as_bytes()
+                    .map_err(sqlx::Error::Decode)?;
+                let scales = numeric_array_display_scales(bytes)?;
+                FieldDef::ArrayValue(ArrayValueDef::StringValues(
+                    values
+                        .into_iter()
+                        .zip(scales)
+                        .map(|(value, scale)| value.with_scale(scale).to_plain_string())
+                        .collect(),
+                ))
+            }
+            None => FieldDef::IsNull(true),
+        },
+        "UUID[]" => get(row, index, |v: Vec<Uuid>| {
+            FieldDef::ArrayValue(ArrayValueDef::StringValues(
+                v.iter().map(Uuid::to_string).collect(),
+            ))
+        })?,
+        _ => {
+            info!(
+                "Unknown field type for column '{}': {type_name}",
+                column.name()
+            );
+            // User-defined types such as enums are sent as text, so try to read them as such
+            match row.try_get_unchecked::<Option<String>, _>(index)? {
+                Some(value) => FieldDef::StringValue(value),
+                None => FieldDef::IsNull(true),
+            }
+        }
+    };
+    Ok(field)
+}
+
+fn bind_parameters<'q>(
+    mut query: Query<'q, Postgres, PgArguments>,
+    params: &[&'q SqlParameterDef],
+) -> Result<Query<'q, Postgres, PgArguments>, anyhow::Error> {
+    for arg in params {
+        let invalid =
+            |e: &dyn std::fmt::Display| anyhow!("Invalid value for parameter '{}': {e}", arg.name);
+
+        query = match (&arg.value, &arg.type_hint) {
+            (FieldDef::ArrayValue(_), _) => {
+                return Err(anyhow!("Array parameters are not supported"));
+            }
+            (FieldDef::BlobValue(b64), _) => {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(b64)
+                    .map_err(|e| {
+                        anyhow!(
+                            "Failed to decode base64 blob for parameter '{}': {e}",
+                            arg.name
+                        )
+                    })?;
+                query.bind(data)
+            }
+            (FieldDef::BooleanValue(x), _) => query.bind(*x),
+            (FieldDef::DoubleValue(x), _) => query.bind(*x),
+            (FieldDef::IsNull(_), _) => query.bind(UntypedNull),
+            (FieldDef::LongValue(x), _) => query.bind(*x),
+            (FieldDef::StringValue(x), None) => query.bind(x.as_str()),
+            (FieldDef::StringValue(x), Some(TypeHintDef::Date)) => {
+                query.bind(NaiveDate::parse_from_str(x, "%Y-%m-%d").map_err(|e| invalid(&e))?)
+            }
+            (FieldDef::StringValue(x), Some(TypeHintDef::Time)) => {
+                query.bind(NaiveTime::parse_from_str(x, "%H:%M:%S%.f").map_err(|e| invalid(&e))?)
+            }
+            (FieldDef::StringValue(x), Some(TypeHintDef::Timestamp)) => query.bind(
+                NaiveDateTime::parse_from_str(x, "%Y-%m-%d %H:%M:%S%.f")
+                    .map_err(|e| invalid(&e))?,
+            ),
+            (FieldDef::StringValue(x), Some(TypeHintDef::Uuid)) => {
+                query.bind(Uuid::parse_str(x).map_err(|e| invalid(&e))?)
+            }
+            (FieldDef::StringValue(x), Some(TypeHintDef::Json)) => query.bind(Json(
+                serde_json::from_str::<serde_json::Value>(x).map_err(|e| invalid(&e))?,
+            )),
+            (FieldDef::StringValue(x), Some(TypeHintDef::Decimal)) => {
+                query.bind(BigDecimal::from_str(x).map_err(|e| invalid(&e))?)
+            }
+        }
+    }
+
+    Ok(query)
+}
+
+/// Run `sql` once for each set of parameters in `params`
+async fn run_statements(
+    conn: &mut PgConnection,
+    sql: &str,
+    args_to_be_bound: &[String],
+    params: &[Vec<SqlParameterDef>],
+) -> Result<QueryOutput, QueryError> {
+    // Statements with a RETURNING clause give back records, like a SELECT
+    let returns_records = is_select(sql) || has_keyword(sql, Dialect::Postgres, "RETURNING");
+
+    let value = if returns_records {
+        let mut collected_records = vec![];
+
+        for row_params in params {
+            let query = ordered_parameters(args_to_be_bound, row_params)
+                .and_then(|ordered| bind_parameters(sqlx::query(sql), &ordered))
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+            let records = query
+                .fetch_all(&mut *conn)
+                .await
+                .inspect_err(|e| error!("Failed to execute query: {e:?}"))
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, describe_error(e)))?;
+
+            collected_records.extend(
+                records
+                    .into_iter()
+                    .filter_map(|row| try_row_to_aws_fields(row).ok()),
+            );
+        }
+
+        Either::Left(collected_records.into())
+    } else {
+        let mut affected_rows = 0;
+        for row_params in params {
+            let query = ordered_parameters(args_to_be_bound, row_params)
+                .and_then(|ordered| bind_parameters(sqlx::query(sql), &ordered))
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+            affected_rows += query
+                .execute(&mut *conn)
+                .await
+                .inspect_err(|e| error!("Failed to execute query: {e:?}"))
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, describe_error(e)))?
+                .rows_affected();
+        }
+
+        Either::Right(affected_rows)
+    };
+
+    Ok(value)
+}
+
+/// A Data API transaction, holding its connection until it ends
+struct PostgresTransaction {
+    transaction: sqlx::Transaction<'static, Postgres>,
+    connection_id: i32,
+}
+
+#[async_trait]
+impl Transaction for PostgresTransaction {
+    async fn run_query(
+        &mut self,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, QueryError> {
+        run_statements(&mut self.transaction, sql, args_to_be_bound, &params).await
+    }
+
+    async fn execute_raw(&mut self, sql: &str) -> anyhow::Result<()> {
+        self.transaction
+            .execute(sqlx::raw_sql(sql))
+            .await
+            .map_err(describe_error)?;
+        Ok(())
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        Some(self.connection_id as u64)
+    }
+
+    async fn commit(self: Box<Self>) -> anyhow::Result<()> {
+        self.transaction.commit().await.map_err(describe_error)
+    }
+
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()> {
+        self.transaction.rollback().await.map_err(describe_error)
+    }
+}
+
+#[async_trait]
+impl Backend for PostgresBackend {
+    fn dialect(&self) -> Dialect {
+        Dialect::Postgres
+    }
+
+    async fn run_query(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, QueryError> {
+        let mut conn = self.connection(database, schema).await?;
+        run_statements(&mut conn, sql, args_to_be_bound, &params).await
+    }
+
+    async fn begin_transaction(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+    ) -> Result<Box<dyn Transaction>, QueryError> {
+        let mut conn = self.connection(database, schema).await?;
+        let connection_id: i32 = sqlx::query("SELECT pg_backend_pid()")
+            .fetch_one(&mut *conn)
+            .await
+            .and_then(|row| row.try_get(0))
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, describe_error(e)))?;
+        let transaction = sqlx::Transaction::begin(conn, None)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, describe_error(e)))?;
+        Ok(Box::new(PostgresTransaction {
+            transaction,
+            connection_id,
+        }))
+    }
+
+    async fn execute_raw(&self, database: &str, sql: &str) -> anyhow::Result<()> {
+        let mut conn = self
+            .waiters
+            .wait(self.pool(Some(database)).acquire())
+            .await?;
+        conn.execute(sqlx::raw_sql("RESET search_path"))
+            .await
+            .map_err(describe_error)?;
+        conn.execute(sqlx::raw_sql(sql))
+            .await
+            .map_err(describe_error)?;
+        Ok(())
+    }
+
+    async fn check_connection(&self) -> anyhow::Result<()> {
+        let connection = self.options.connect().await?;
+        connection.close().await?;
+        Ok(())
+    }
+
+    async fn create_database(&self, database: &str) -> anyhow::Result<()> {
+        // PostgreSQL has no CREATE DATABASE IF NOT EXISTS
+        let pool = self.pool(None);
+        let exists = sqlx::query("SELECT 1 FROM pg_database WHERE datname = $1")
+            .bind(database)
+            .fetch_optional(&pool)
+            .await
+            .map_err(describe_error)?
+            .is_some();
+        if !exists {
+            info!("Creating database '{database}'");
+            sqlx::raw_sql(&format!("CREATE DATABASE {}", quote_identifier(database)))
+                .execute(&pool)
+                .await
+                .map_err(describe_error)?;
+        }
+        Ok(())
+    }
+
+    async fn clone_database(&self, template: &str, database: &str) -> anyhow::Result<()> {
+        self.close_pools(template).await;
+        self.close_pools(database).await;
+        let mut conn = self.maintenance_connection().await?;
+        for sql in [
+            format!(
+                "DROP DATABASE IF EXISTS {} WITH (FORCE)",
+                quote_identifier(database)
+            ),
+            format!(
+                "CREATE DATABASE {} TEMPLATE {}",
+                quote_identifier(database),
+                quote_identifier(template)
+            ),
+        ] {
+            conn.execute(sqlx::raw_sql(&sql))
+                .await
+                .map_err(describe_error)?;
+        }
+        Ok(())
+    }
+
+    async fn drop_database(&self, database: &str) -> anyhow::Result<()> {
+        self.close_pools(database).await;
+        let sql = format!(
+            "DROP DATABASE IF EXISTS {} WITH (FORCE)",
+            quote_identifier(database)
+        );
+        let mut conn = self.maintenance_connection().await?;
+        conn.execute(sqlx::raw_sql(&sql))
+            .await
+            .map_err(describe_error)?;
+        Ok(())
+    }
+
+    async fn snapshot_database(&self, database: &str) -> anyhow::Result<()> {
+        self.clone_database(database, &snapshot_name(database))
+            .await
+    }
+
+    async fn restore_database(&self, database: &str) -> anyhow::Result<()> {
+        let snapshot = snapshot_name(database);
+        let mut conn = self.maintenance_connection().await?;
+        if !Self::database_exists(&mut conn, &snapshot).await? {
+            return Err(anyhow!("No snapshot of database '{database}'"));
+        }
+        self.clone_database(&snapshot, database).await
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool(None))
+            .await
+            .map_err(describe_error)?;
+        Ok(())
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        let mut stats = PoolStats {
+            waiting: self.waiters.count(),
+            ..PoolStats::default()
+        };
+        for (_, pool) in self.pools.lock().unwrap().iter() {
+            stats.add_pool(pool);
+        }
+        stats
+    }
+
+    async fn close(&self) {
+        let pools: Vec<_> = self
+            .pools
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, pool)| pool)
+            .collect();
+        for pool in pools {
+            pool.close().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The server of the tests needing one, e.g. `DATABASE_URL=postgres://postgres@localhost/postgres`
+    fn database_url() -> Option<String> {
+        std::env::var("DATABASE_URL")
+            .ok()
+            .filter(|url| url.starts_with("postgres"))
+    }
+
+    #[test]
+    fn test_numeric_display_scale() {
+        // 12.50: 2 base 10000 digits, weight 0, positive, displayed with 2 decimals, 12 and 5000
+        let bytes = [0, 2, 0, 0, 0, 0, 0, 2, 0, 12, 0x13, 0x88];
+        assert_eq!(numeric_display_scale(&bytes).unwrap(), 2);
+        assert!(numeric_display_scale(&bytes[..7]).is_err());
+
+        // {12.50, 3}: 1 dimension of 2 elements from index 1, each after its length
+        let mut array = vec![0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 6, 164, 0, 0, 0, 2, 0, 0, 0, 1];
+        array.extend([0, 0, 0, 12]);
+        array.extend(bytes);
+        array.extend([0, 0, 0, 10, 0, 1, 0, 0, 0, 0, 0, 0, 0, 3]);
+        assert_eq!(numeric_array_display_scales(&array).unwrap(), vec![2, 0]);
+        assert!(numeric_array_display_scales(&array[..30]).is_err());
+        assert_eq!(
+            numeric_array_display_scales(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 6, 164]).unwrap(),
+            Vec::<i64>::new()
+        );
+    }
+
+    #[test]
+    fn test_bind_parameters() {
+        let parameter = |value, type_hint| SqlParameterDef {
+            name: "p".to_string(),
+            value,
+            type_hint,
+        };
+        let message = |parameter: &SqlParameterDef| match bind_parameters(
+            sqlx::query("SELECT $1"),
+            &[parameter],
+        ) {
+            Ok(_) => panic!("The parameter was bound"),
+            Err(e) => e.to_string(),
+        };
+
+        let array = parameter(
+            FieldDef::ArrayValue(ArrayValueDef::LongValues(vec![1])),
+            None,
+        );
+        assert_eq!(message(&array), "Array parameters are not supported");
+        let date = parameter(
+            FieldDef::StringValue("2024-13-01".to_string()),
+            Some(TypeHintDef::Date),
+        );
+        assert!(message(&date).starts_with("Invalid value for parameter 'p': "));
+        let blob = parameter(FieldDef::BlobValue("not base64!".to_string()), None);
+        assert!(message(&blob).starts_with("Failed to decode base64 blob for parameter 'p': "));
+    }
+
+    #[test]
+    fn test_describe_error() {
+        // Errors not coming from the server are left as they are
+        let e = describe_error(sqlx::Error::RowNotFound);
+        assert_eq!(e.to_string(), sqlx::Error::RowNotFound.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_pools_are_capped() {
+        // Pools are lazy, so no server is needed
+        let backend = PostgresBackend::connect_lazy("postgres://localhost/app").unwrap();
+        for i in 0..MAX_POOLS + 4 {
+            backend.pool(Some(&format!("db_{i}")));
+            // The first database stays the most recently used one
+            backend.pool(Some("db_0"));
+        }
+        let pools = backend.pools.lock().unwrap();
+        assert_eq!(pools.len(), MAX_POOLS);
+        assert!(pools.contains_key(&Some("db_0".to_string())));
+        assert!(!pools.contains_key(&Some("db_1".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_types() {
+        let Some(url) = database_url() else {
+            return;
+        };
+        let backend = PostgresBackend::connect_lazy(&url).unwrap();
+        let sql = "SELECT 12.50::numeric, NULL::numeric, 1::int2, 2::int8, 1.5::float8, true, \
+                   'x'::varchar, '{\"a\": 1}'::jsonb, '2024-01-02'::date, \
+                   '2024-01-02 03:04:05.5'::timestamp, '\\x0102'::bytea, ARRAY[1, 2]::int4[], \
+                   ARRAY[1.50, 2]::numeric[]";
+        let output = backend
+            .run_query(None, None, sql, &[], vec![vec![]])
+            .await
+            .unwrap();
+        let Either::Left(records) = output else {
+            panic!("SELECT returned no records");
+        };
+        assert_eq!(
+            records.rows[0],
+            vec![
+                FieldDef::StringValue("12.50".to_string()),
+                FieldDef::IsNull(true),
+                FieldDef::LongValue(1),
+                FieldDef::LongValue(2),
+                FieldDef::DoubleValue(1.5),
+                FieldDef::BooleanValue(true),
+                FieldDef::StringValue("x".to_string()),
+                FieldDef::StringValue(r#"{"a":1}"#.to_string()),
+                FieldDef::StringValue("2024-01-02".to_string()),
+                FieldDef::StringValue("2024-01-02 03:04:05.500".to_string()),
+                FieldDef::BlobValue("AQI=".to_string()),
+                FieldDef::ArrayValue(ArrayValueDef::LongValues(vec![1, 2])),
+                FieldDef::ArrayValue(ArrayValueDef::StringValues(vec![
+                    "1.50".to_string(),
+                    "2".to_string()
+                ])),
+            ]
+        );
+
+        let (status, e) = backend
+            .run_query(None, None, "SELECT * FROM missing", &[], vec![vec![]])
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            e.to_string(),
+            "ERROR: relation \"missing\" does not exist\n  Position: 15; SQLState: 42P01"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clone_database() {
+        let Some(url) = database_url() else {
+            return;
+        };
+        let backend = PostgresBackend::connect_lazy(&url).unwrap();
+        let template = format!("rds_lite_template_{}", std::process::id());
+        let clone = format!("{template}_clone");
+        backend.create_database(&template).await.unwrap();
+        backend
+            .execute_raw(
+                &template,
+                "CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1)",
+            )
+            .await
+            .unwrap();
+        backend.create_database(&clone).await.unwrap();
+
+        // A session still connected to the clone is terminated to replace it
+        let mut session = PgConnection::connect_with(&backend.options.clone().database(&clone))
+            .await
+            .unwrap();
+        backend.clone_database(&template, &clone).await.unwrap();
+        assert!(session.execute("SELECT 1").await.is_err());
+
+        let output = backend
+            .run_query(Some(&clone), None, "SELECT x FROM t", &[], vec![vec![]])
+            .await
+            .unwrap();
+        let Either::Left(records) = output else {
+            panic!("SELECT returned no records");
+        };
+        assert_eq!(records.rows, vec![vec![FieldDef::LongValue(1)]]);
+
+        backend.drop_database(&clone).await.unwrap();
+        backend.drop_database(&template).await.unwrap();
+    }
+}
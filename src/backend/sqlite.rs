@@ -0,0 +1,584 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use base64::Engine as _;
+use log::error;
+use sqlx::pool::PoolConnection;
+use sqlx::query::Query;
+use sqlx::sqlite::{
+    SqliteArguments, SqliteColumn, SqliteConnectOptions, SqliteConnection, SqlitePool, SqliteRow,
+};
+use sqlx::{Column, Either, Executor, Row, Sqlite, TypeInfo, ValueRef};
+
+use crate::aws::{FieldDef, SqlParameterDef};
+use crate::backend::{
+    Backend, PoolStats, Transaction, Waiters, acquire_error, close_pool, pool_options,
+};
+use crate::config::PoolConfig;
+use crate::query::{Dialect, QueryError, QueryOutput, is_select, ordered_parameters};
+
+/// Name of the database used by requests that don't specify one
+const DEFAULT_DATABASE: &str = "default";
+
+enum Location {
+    /// Every database lives in memory and is lost when rds-lite exits
+    Memory,
+    /// Every database is a `<name>.sqlite3` file in this directory
+    Directory(PathBuf),
+}
+
+/// Runs statements on embedded SQLite databases, so that no database server is needed.
+/// Each `database` name maps to its own SQLite database, created on first use.
+pub struct SqliteBackend {
+    location: Location,
+    pool_config: PoolConfig,
+    pools: Mutex<HashMap<String, SqlitePool>>,
+    waiters: Waiters,
+    /// Tells apart the snapshot files of in-memory databases of different backends
+    id: u64,
+}
+
+impl SqliteBackend {
+    /// Keep all databases in memory
+    pub fn in_memory() -> Self {
+        Self {
+            location: Location::Memory,
+            pool_config: PoolConfig::default(),
+            pools: Mutex::new(HashMap::new()),
+            waiters: Waiters::default(),
+            id: rand::random(),
+        }
+    }
+
+    /// Store databases as files inside `directory`
+    pub fn in_directory(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            location: Location::Directory(directory.into()),
+            pool_config: PoolConfig::default(),
+            pools: Mutex::new(HashMap::new()),
+            waiters: Waiters::default(),
+            id: rand::random(),
+        }
+    }
+
+    /// Accepts `sqlite::memory:` or `sqlite://<directory>`
+    pub fn connect_lazy(url: &str) -> Result<Self, sqlx::Error> {
+        let path = url.trim_start_matches("sqlite:").trim_start_matches("//");
+        match path {
+            ":memory:" => Ok(Self::in_memory()),
+            "" => Err(sqlx::Error::Configuration(
+                "SQLite URL must be sqlite::memory: or sqlite://<directory>".into(),
+            )),
+            directory => Ok(Self::in_directory(directory)),
+        }
+    }
+
+    /// Like `connect_lazy`, with pools sized by `pool`. In-memory databases still have a
+    /// single connection each.
+    pub fn connect_lazy_with_pool(url: &str, pool: &PoolConfig) -> Result<Self, sqlx::Error> {
+        let mut backend = Self::connect_lazy(url)?;
+        backend.pool_config = *pool;
+        Ok(backend)
+    }
+
+    fn pool(&self, database: Option<&str>) -> Result<SqlitePool, anyhow::Error> {
+        let database = database.unwrap_or(DEFAULT_DATABASE);
+        if database.is_empty() || database.contains(['/', '\\']) || database.starts_with('.') {
+            return Err(anyhow!("Invalid database name '{database}'"));
+        }
+
+        let mut pools = self.pools.lock().unwrap();
+        if let Some(pool) = pools.get(database) {
+            return Ok(pool.clone());
+        }
+
+        let pool = match &self.location {
+            // An in-memory database only lives as long as its connection, so keep
+            // exactly one connection open forever
+            Location::Memory => pool_options(&self.pool_config)
+                .max_connections(1)
+                .min_connections(0)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                // `:memory:` rather than `in_memory(true)`, whose SQLITE_OPEN_MEMORY flag would
+                // also keep snapshot files in memory
+                .connect_lazy_with(
+                    SqliteConnectOptions::new()
+                        .filename(":memory:")
+                        .statement_cache_capacity(self.pool_config.statement_cache_capacity),
+                ),
+            Location::Directory(directory) => pool_options(&self.pool_config).connect_lazy_with(
+                SqliteConnectOptions::new()
+                    .filename(directory.join(format!("{database}.sqlite3")))
+                    .create_if_missing(true)
+                    .statement_cache_capacity(self.pool_config.statement_cache_capacity),
+            ),
+        };
+        pools.insert(database.to_string(), pool.clone());
+        Ok(pool)
+    }
+
+    /// A connection of the pool of `database`
+    async fn connection(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+    ) -> Result<PoolConnection<Sqlite>, QueryError> {
+        if schema.is_some() {
+            return Err((
+                StatusCode::NOT_IMPLEMENTED,
+                anyhow!("Schema selection is not supported"),
+            ));
+        }
+
+        let pool = self
+            .pool(database)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        self.waiters
+            .wait(pool.acquire())
+            .await
+            .map_err(|e| acquire_error(&pool, e))
+    }
+
+    /// Write a copy of `database` to the file at `path`, replacing it
+    async fn save_to(&self, database: &str, path: &Path) -> Result<(), anyhow::Error> {
+        let pool = self.pool(Some(database))?;
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        // VACUUM INTO refuses to overwrite a file
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+
+        let mut conn = self.waiters.wait(pool.acquire()).await?;
+        let sql = format!(
+            "VACUUM INTO '{}'",
+            path.to_string_lossy().replace('\'', "''")
+        );
+        conn.execute(sqlx::raw_sql(&sql)).await?;
+        Ok(())
+    }
+
+    /// Replace the content of `database` by the one of the database file at `path`
+    async fn load_from(&self, database: &str, path: &Path) -> Result<(), anyhow::Error> {
+        let pool = self.pool(Some(database))?;
+        let mut conn = self.waiters.wait(pool.acquire()).await?;
+        let attach = format!(
+            "ATTACH DATABASE '{}' AS snapshot",
+            path.to_string_lossy().replace('\'', "''")
+        );
+        conn.execute(sqlx::raw_sql(&attach)).await?;
+
+        let loaded = async {
+            conn.execute(sqlx::raw_sql("PRAGMA foreign_keys = OFF; BEGIN"))
+                .await?;
+            let copied = copy_from_snapshot(&mut conn).await;
+            let end = if copied.is_ok() { "COMMIT" } else { "ROLLBACK" };
+            conn.execute(sqlx::raw_sql(end)).await?;
+            copied
+        }
+        .await;
+        if loaded.is_err() {
+            // A failed COMMIT leaves the transaction open, which would keep `snapshot` in use
+            let _ = conn.execute(sqlx::raw_sql("ROLLBACK")).await;
+        }
+        // Detached whatever happened, as the next snapshot or restore on this connection
+        // attaches it again
+        let detached = conn
+            .execute(sqlx::raw_sql(
+                "PRAGMA foreign_keys = ON; DETACH DATABASE snapshot",
+            ))
+            .await;
+        loaded?;
+        detached?;
+        Ok(())
+    }
+
+    /// File the snapshot of `database` is saved to
+    fn snapshot_path(&self, database: &str) -> PathBuf {
+        match &self.location {
+            Location::Memory => std::env::temp_dir().join(format!(
+                "rds-lite-{:x}-{database}.snapshot.sqlite3",
+                self.id
+            )),
+            Location::Directory(directory) => directory
+                .join(".snapshots")
+                .join(format!("{database}.sqlite3")),
+        }
+    }
+}
+
+impl Drop for SqliteBackend {
+    fn drop(&mut self) {
+        // Snapshots of in-memory databases would outlive them otherwise
+        if let Location::Memory = self.location {
+            for database in self.pools.lock().unwrap().keys() {
+                let _ = std::fs::remove_file(self.snapshot_path(database));
+            }
+        }
+    }
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Replace the content of the `main` database of `conn` by the one of the `snapshot`
+/// database attached to it
+async fn copy_from_snapshot(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    let existing: Vec<(String, String)> = sqlx::query_as(
+        "SELECT type, name FROM main.sqlite_master
+         WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%'",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for (kind, name) in existing {
+        // Indexes and triggers are dropped along with their table
+        let kind = if kind == "view" { "VIEW" } else { "TABLE" };
+        let sql = format!("DROP {kind} IF EXISTS main.{}", quote_identifier(&name));
+        conn.execute(sqlx::raw_sql(&sql)).await?;
+    }
+
+    // Tables come first, so that the indexes, triggers and views can refer to them
+    let objects: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT type, name, sql FROM snapshot.sqlite_master
+         WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%'
+         ORDER BY type = 'table' DESC, rowid",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for (kind, name, sql) in objects {
+        conn.execute(sqlx::raw_sql(&sql)).await?;
+        if kind == "table" {
+            let name = quote_identifier(&name);
+            let copy = format!("INSERT INTO main.{name} SELECT * FROM snapshot.{name}");
+            conn.execute(sqlx::raw_sql(&copy)).await?;
+        }
+    }
+
+    let has_sequences: Option<(i64,)> =
+        sqlx::query_as("SELECT 1 FROM snapshot.sqlite_master WHERE name = 'sqlite_sequence'")
+            .fetch_optional(&mut *conn)
+            .await?;
+    if has_sequences.is_some() {
+        conn.execute(sqlx::raw_sql(
+            "DELETE FROM main.sqlite_sequence;
+             INSERT INTO main.sqlite_sequence SELECT * FROM snapshot.sqlite_sequence",
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+fn try_row_to_aws_fields(row: SqliteRow) -> Result<Vec<FieldDef>, sqlx::Error> {
+    let columns = row.columns();
+    let mut values = Vec::new();
+
+    for column in columns {
+        let field = column_into_fielddef(&row, column).inspect_err(|e| {
+            error!(
+                "Error converting column '{}' to FieldDef: {e}",
+                column.name()
+            );
+        })?;
+
+        values.push(field);
+    }
+
+    Ok(values)
+}
+
+fn column_into_fielddef(row: &SqliteRow, column: &SqliteColumn) -> Result<FieldDef, sqlx::Error> {
+    let index = column.ordinal();
+
+    // SQLite is dynamically typed: the declared column type is only a hint, so the
+    // conversion is driven by the storage class of the value itself
+    let value = row.try_get_raw(index)?;
+    if value.is_null() {
+        return Ok(FieldDef::IsNull(true));
+    }
+    let storage_class = value.type_info().name().to_string();
+
+    let field = match (column.type_info().name(), storage_class.as_str()) {
+        ("BOOLEAN", "INTEGER") => FieldDef::BooleanValue(row.try_get_unchecked(index)?),
+        (_, "INTEGER") => FieldDef::LongValue(row.try_get_unchecked(index)?),
+        (_, "REAL") => FieldDef::DoubleValue(row.try_get_unchecked(index)?),
+        (_, "BLOB") => {
+            let value: Vec<u8> = row.try_get_unchecked(index)?;
+            FieldDef::BlobValue(base64::engine::general_purpose::STANDARD.encode(&value))
+        }
+        _ => FieldDef::StringValue(row.try_get_unchecked(index)?),
+    };
+    Ok(field)
+}
+
+fn bind_parameters<'q>(
+    mut query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    params: &[&'q SqlParameterDef],
+) -> Result<Query<'q, Sqlite, SqliteArguments<'q>>, anyhow::Error> {
+    for arg in params {
+        query = match &arg.value {
+            FieldDef::ArrayValue(_) => {
+                return Err(anyhow!("Array parameters are not supported"));
+            }
+            FieldDef::BlobValue(b64) => {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(b64)
+                    .map_err(|e| {
+                        anyhow!(
+                            "Failed to decode base64 blob for parameter '{}': {e}",
+                            arg.name
+                        )
+                    })?;
+                query.bind(data)
+            }
+            FieldDef::BooleanValue(x) => query.bind(*x),
+            FieldDef::DoubleValue(x) => query.bind(*x),
+            FieldDef::IsNull(_) => query.bind(None::<String>),
+            FieldDef::LongValue(x) => query.bind(*x),
+            FieldDef::StringValue(x) => query.bind(x.as_str()),
+        }
+    }
+
+    Ok(query)
+}
+
+/// Run `sql` once for each set of parameters in `params`
+async fn run_statements(
+    conn: &mut SqliteConnection,
+    sql: &str,
+    args_to_be_bound: &[String],
+    params: &[Vec<SqlParameterDef>],
+) -> Result<QueryOutput, QueryError> {
+    let value = if is_select(sql) {
+        let mut collected_records = vec![];
+
+        for row_params in params {
+            let query = ordered_parameters(args_to_be_bound, row_params)
+                .and_then(|ordered| bind_parameters(sqlx::query(sql), &ordered))
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+            let records = query
+                .fetch_all(&mut *conn)
+                .await
+                .inspect_err(|e| error!("Failed to execute query: {e:?}"))
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+
+            collected_records.extend(
+                records
+                    .into_iter()
+                    .filter_map(|row| try_row_to_aws_fields(row).ok()),
+            );
+        }
+
+        Either::Left(collected_records.into())
+    } else {
+        let mut affected_rows = 0;
+        for row_params in params {
+            let query = ordered_parameters(args_to_be_bound, row_params)
+                .and_then(|ordered| bind_parameters(sqlx::query(sql), &ordered))
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+            affected_rows += query
+                .execute(&mut *conn)
+                .await
+                .inspect_err(|e| error!("Failed to execute query: {e:?}"))
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?
+                .rows_affected();
+        }
+
+        Either::Right(affected_rows)
+    };
+
+    Ok(value)
+}
+
+/// A Data API transaction, holding its connection until it ends. With in-memory databases,
+/// other requests to the same database wait for it to end.
+struct SqliteTransaction(sqlx::Transaction<'static, Sqlite>);
+
+#[async_trait]
+impl Transaction for SqliteTransaction {
+    async fn run_query(
+        &mut self,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, QueryError> {
+        run_statements(&mut self.0, sql, args_to_be_bound, &params).await
+    }
+
+    async fn execute_raw(&mut self, sql: &str) -> anyhow::Result<()> {
+        self.0.execute(sqlx::raw_sql(sql)).await?;
+        Ok(())
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        None
+    }
+
+    async fn commit(self: Box<Self>) -> anyhow::Result<()> {
+        self.0.commit().await?;
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()> {
+        self.0.rollback().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for SqliteBackend {
+    fn dialect(&self) -> Dialect {
+        Dialect::Sqlite
+    }
+
+    async fn run_query(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+        sql: &str,
+        args_to_be_bound: &[String],
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, QueryError> {
+        let mut conn = self.connection(database, schema).await?;
+        run_statements(&mut conn, sql, args_to_be_bound, &params).await
+    }
+
+    async fn begin_transaction(
+        &self,
+        database: Option<&str>,
+        schema: Option<&str>,
+    ) -> Result<Box<dyn Transaction>, QueryError> {
+        let conn = self.connection(database, schema).await?;
+        let transaction = sqlx::Transaction::begin(conn, None)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+        Ok(Box::new(SqliteTransaction(transaction)))
+    }
+
+    async fn execute_raw(&self, database: &str, sql: &str) -> anyhow::Result<()> {
+        let pool = self.pool(Some(database))?;
+        let mut conn = self.waiters.wait(pool.acquire()).await?;
+        conn.execute(sqlx::raw_sql(sql)).await?;
+        Ok(())
+    }
+
+    async fn create_database(&self, database: &str) -> anyhow::Result<()> {
+        // Databases are created when first used
+        self.pool(Some(database))?;
+        Ok(())
+    }
+
+    async fn clone_database(&self, template: &str, database: &str) -> anyhow::Result<()> {
+        let exists = match &self.location {
+            Location::Memory => self.pools.lock().unwrap().contains_key(template),
+            Location::Directory(directory) => {
+                directory.join(format!("{template}.sqlite3")).is_file()
+            }
+        };
+        if !exists {
+            return Err(anyhow!("Unknown database '{template}'"));
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("rds-lite-{:x}-{database}.clone.sqlite3", self.id));
+        let copied = match self.save_to(template, &path).await {
+            Ok(()) => self.load_from(database, &path).await,
+            Err(e) => Err(e),
+        };
+        let _ = std::fs::remove_file(&path);
+        copied
+    }
+
+    async fn drop_database(&self, database: &str) -> anyhow::Result<()> {
+        let pool = self.pools.lock().unwrap().remove(database);
+        if let Some(pool) = pool {
+            close_pool(&pool, database).await;
+        }
+        let mut files = vec![self.snapshot_path(database)];
+        if let Location::Directory(directory) = &self.location {
+            for suffix in ["", "-wal", "-shm"] {
+                files.push(directory.join(format!("{database}.sqlite3{suffix}")));
+            }
+        }
+        for file in files {
+            match std::fs::remove_file(&file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn snapshot_database(&self, database: &str) -> anyhow::Result<()> {
+        self.save_to(database, &self.snapshot_path(database)).await
+    }
+
+    async fn restore_database(&self, database: &str) -> anyhow::Result<()> {
+        let path = self.snapshot_path(database);
+        if !path.is_file() {
+            return Err(anyhow!("No snapshot of database '{database}'"));
+        }
+        self.load_from(database, &path).await
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool(None)?).await?;
+        Ok(())
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        let mut stats = PoolStats {
+            waiting: self.waiters.count(),
+            ..PoolStats::default()
+        };
+        for pool in self.pools.lock().unwrap().values() {
+            stats.add_pool(pool);
+        }
+        stats
+    }
+
+    async fn close(&self) {
+        let pools: Vec<_> = self
+            .pools
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, pool)| pool)
+            .collect();
+        for pool in pools {
+            pool.close().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_restore_after_failure() {
+        let backend = SqliteBackend::in_memory();
+        backend
+            .execute_raw("app", "CREATE TABLE t (x INTEGER)")
+            .await
+            .unwrap();
+        backend.snapshot_database("app").await.unwrap();
+        let path = backend.snapshot_path("app");
+        let snapshot = std::fs::read(&path).unwrap();
+
+        // A broken snapshot fails to load, but leaves the connection able to load another
+        std::fs::write(&path, vec![b'x'; snapshot.len()]).unwrap();
+        assert!(backend.restore_database("app").await.is_err());
+        std::fs::write(&path, snapshot).unwrap();
+        backend.restore_database("app").await.unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use crate::backend::{self, Backend};
+use crate::config::Config;
+
+/// An emulated Aurora cluster
+pub struct Cluster {
+    pub name: String,
+    /// Requests with this `resourceArn` are routed to the cluster, or all the unmatched
+    /// requests if `None`
+    pub resource_arn: Option<String>,
+    pub backend: Arc<dyn Backend>,
+}
+
+/// The clusters requests are routed to, by their `resourceArn`
+#[derive(Default)]
+pub struct Clusters {
+    clusters: Vec<Arc<Cluster>>,
+}
+
+impl Clusters {
+    /// Create the clusters described by `config`. No connection is opened until the first
+    /// statement is run on each of them.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut clusters = Self::default();
+        for cluster in config.all_clusters() {
+            let pool = cluster.pool.unwrap_or(config.pool);
+            let backend = backend::connect_lazy(&cluster.database_url, &pool)
+                .with_context(|| format!("Invalid database_url for cluster '{}'", cluster.name))?;
+            clusters.add(Cluster {
+                name: cluster.name,
+                resource_arn: cluster.resource_arn,
+                backend,
+            });
+        }
+        Ok(clusters)
+    }
+
+    /// Add a cluster, replacing the one with the same name if any
+    pub fn add(&mut self, cluster: Cluster) {
+        self.clusters.retain(|c| c.name != cluster.name);
+        self.clusters.push(Arc::new(cluster));
+    }
+
+    /// The cluster with the given `resourceArn`, falling back to the default cluster
+    pub fn get(&self, resource_arn: Option<&str>) -> Option<&Arc<Cluster>> {
+        resource_arn
+            .and_then(|arn| {
+                self.clusters
+                    .iter()
+                    .find(|c| c.resource_arn.as_deref() == Some(arn))
+            })
+            .or_else(|| self.clusters.iter().find(|c| c.resource_arn.is_none()))
+    }
+
+    /// The cluster called `name`
+    pub fn named(&self, name: &str) -> Option<&Arc<Cluster>> {
+        self.clusters.iter().find(|c| c.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Cluster>> {
+        self.clusters.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clusters.is_empty()
+    }
+}
@@ -0,0 +1,859 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Environment variable with the path of the configuration file
+pub const CONFIG_ENV_VAR: &str = "RDS_LITE_CONFIG";
+
+/// Prefix of environment variables overriding individual configuration keys,
+/// e.g. `RDS_LITE_PORT=4000`. Nested keys are separated by `__`.
+const ENV_OVERRIDE_PREFIX: &str = "RDS_LITE_";
+
+/// Settings rds-lite is started with
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address the HTTP server listens on, IPv4 or IPv6
+    pub bind_address: IpAddr,
+    /// Port the HTTP server listens on
+    pub port: u16,
+    /// How long `/ready` waits for each cluster to answer
+    pub ready_timeout_ms: u64,
+    /// Append every Data API request and its response to this JSON Lines file
+    pub record_path: Option<PathBuf>,
+    /// Answer Data API requests from this recording instead of running them on a database
+    pub replay_path: Option<PathBuf>,
+    /// How requests are matched against the recording in `replay_path`
+    pub replay_match: ReplayMatch,
+    /// Shorthand for a cluster named "default" that serves every request whose
+    /// `resourceArn` doesn't match any of `clusters`
+    pub database_url: Option<String>,
+    pub clusters: Vec<ClusterConfig>,
+    /// Failures injected into matching requests, to test client resilience
+    pub faults: Vec<FaultConfig>,
+    /// Rate limits answering `ThrottlingException` when exceeded
+    pub throttling: ThrottlingConfig,
+    /// SQL scripts run on databases at startup
+    pub fixtures: Vec<FixturesConfig>,
+    /// How long the databases cloned by `/_admin/databases/{name}/clone` live unless
+    /// released earlier
+    pub ephemeral_ttl_ms: u64,
+    /// Expiry of the transactions started with `BeginTransaction`
+    pub transactions: TransactionsConfig,
+    /// How many of the last executed statements `/_admin/statements` keeps, 0 to keep none
+    pub statement_log_size: usize,
+    /// How long in-flight requests are given to complete when the server is stopped
+    pub shutdown_timeout_ms: u64,
+    /// Serve HTTPS, instead of or alongside HTTP
+    pub tls: Option<TlsConfig>,
+    /// Connection pools of the clusters without a `pool` of their own
+    pub pool: PoolConfig,
+    /// Wait for the databases to accept connections before serving, instead of failing the
+    /// first requests while they start
+    pub wait_for_databases: Option<WaitConfig>,
+}
+
+/// Keys of `Config`, the only ones `RDS_LITE_*` variables override, so that unrelated
+/// variables sharing the prefix, e.g. `RDS_LITE_VERSION` set by a deploy script, don't
+/// stop rds-lite from starting
+const CONFIG_KEYS: &[&str] = &[
+    "bind_address",
+    "port",
+    "ready_timeout_ms",
+    "record_path",
+    "replay_path",
+    "replay_match",
+    "database_url",
+    "clusters",
+    "faults",
+    "throttling",
+    "fixtures",
+    "ephemeral_ttl_ms",
+    "transactions",
+    "statement_log_size",
+    "shutdown_timeout_ms",
+    "tls",
+    "pool",
+    "wait_for_databases",
+];
+
+/// An emulated Aurora cluster
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClusterConfig {
+    /// Name used to refer to the cluster in logs and responses
+    pub name: String,
+    /// Requests with this `resourceArn` are sent to the cluster. The cluster without one,
+    /// if any, serves the requests that don't match any other cluster.
+    pub resource_arn: Option<String>,
+    /// Database statements are run on, the URL scheme picks the backend
+    pub database_url: String,
+    /// Emulate an Aurora Serverless cluster pausing after this long without requests
+    pub auto_pause_after_ms: Option<u64>,
+    /// How long a paused cluster answers `DatabaseResumingException` once a request wakes it up
+    #[serde(default = "default_resume_time_ms")]
+    pub resume_time_ms: u64,
+    /// Connection pool of the cluster, replacing the top-level `pool`
+    pub pool: Option<PoolConfig>,
+}
+
+/// Sizing of a connection pool. PostgreSQL and SQLite have a pool per database, each of
+/// this size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    /// Connections kept open even when unused
+    pub min_connections: u32,
+    /// How long a request waits for a connection when they're all in use, before failing
+    /// with a retriable `ServiceUnavailableError`
+    pub acquire_timeout_ms: u64,
+    /// Close connections unused for this long, 0 to keep them
+    pub idle_timeout_ms: u64,
+    /// Close connections opened this long ago, 0 to keep them
+    pub max_lifetime_ms: u64,
+    /// Prepared statements kept by each connection for reuse, 0 to close them after each use
+    pub statement_cache_capacity: usize,
+}
+
+impl Default for PoolConfig {
+    /// The defaults of sqlx
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout_ms: 30_000,
+            idle_timeout_ms: 600_000,
+            max_lifetime_ms: 1_800_000,
+            statement_cache_capacity: 100,
+        }
+    }
+}
+
+impl PoolConfig {
+    fn validate(&self) -> Result<()> {
+        if self.max_connections == 0 || self.acquire_timeout_ms == 0 {
+            bail!("Pool max_connections and acquire_timeout_ms must be positive");
+        }
+        if self.min_connections > self.max_connections {
+            bail!(
+                "Pool min_connections ({}) is more than max_connections ({})",
+                self.min_connections,
+                self.max_connections
+            );
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn default_resume_time_ms() -> u64 {
+    30_000
+}
+
+/// A directory of `.sql` files seeding a database. The files are run in lexical order,
+/// each of them once.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixturesConfig {
+    /// Cluster the database belongs to, defaults to the cluster without a `resource_arn`
+    pub cluster: Option<String>,
+    /// Database to seed, created if missing
+    pub database: String,
+    pub directory: PathBuf,
+    /// Snapshot the database once seeded, so that `POST /_admin/databases/{name}/reset`
+    /// brings it back to its seeded state
+    #[serde(default)]
+    pub snapshot: bool,
+}
+
+/// A fault injected into the Data API requests matching all of its conditions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FaultConfig {
+    /// Data API action, e.g. `ExecuteStatement` or `BatchExecuteStatement`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    /// Regular expression searched in the SQL of the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sql: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_arn: Option<String>,
+    /// Chance that a matching request gets the fault, from 0 to 1
+    #[serde(default = "default_probability")]
+    pub probability: f64,
+    /// Only inject the fault into the Nth matching request, counting from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_call: Option<u64>,
+    pub inject: Fault,
+}
+
+fn default_probability() -> f64 {
+    1.0
+}
+
+/// What happens to a request a fault is injected into
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Fault {
+    /// Answer an AWS error, e.g. `StatementTimeoutException`, without running the statement
+    Error {
+        error: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        /// Defaults to the status AWS uses for `error`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<u16>,
+    },
+    /// Wait before running the statement
+    Latency { latency_ms: u64 },
+    /// Drop the connection without running the statement
+    ConnectionReset,
+    /// Run the statement, then answer an AWS error instead of its result
+    PartialFailure {
+        error: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<u16>,
+    },
+}
+
+impl FaultConfig {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(sql) = &self.sql {
+            regex::Regex::new(sql).with_context(|| format!("Invalid sql regex '{sql}'"))?;
+        }
+        if !(0.0..=1.0).contains(&self.probability) {
+            bail!("Fault probability must be between 0 and 1");
+        }
+        if self.on_call == Some(0) {
+            bail!("Fault on_call counts from 1");
+        }
+        if let Fault::Error {
+            status: Some(status),
+            ..
+        }
+        | Fault::PartialFailure {
+            status: Some(status),
+            ..
+        } = self.inject
+            && axum::http::StatusCode::from_u16(status).is_err()
+        {
+            bail!("Invalid fault status {status}");
+        }
+        Ok(())
+    }
+}
+
+/// Token bucket rate limits on the Data API requests. A request is throttled when any of
+/// the limits applying to it is exceeded.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThrottlingConfig {
+    /// Limit on all the requests
+    pub global: Option<RateLimit>,
+    /// Limit on the requests of each `secretArn`
+    pub per_secret: Option<RateLimit>,
+    /// Limit on the requests of each `resourceArn`
+    pub per_resource: Option<RateLimit>,
+    /// Limits replacing `per_secret` or `per_resource` for specific ARNs
+    pub overrides: Vec<RateLimitOverride>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    /// Requests per second
+    pub rate: f64,
+    /// Requests that can be made at once after a quiet period, defaults to `rate`
+    pub burst: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitOverride {
+    /// A secret or resource ARN
+    pub arn: String,
+    pub rate: f64,
+    pub burst: Option<f64>,
+}
+
+impl RateLimitOverride {
+    pub fn limit(&self) -> RateLimit {
+        RateLimit {
+            rate: self.rate,
+            burst: self.burst,
+        }
+    }
+}
+
+impl RateLimit {
+    /// Size of the bucket, at least one request
+    pub fn capacity(&self) -> f64 {
+        self.burst.unwrap_or(self.rate).max(1.0)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let positive = |value: f64| value.is_finite() && value > 0.0;
+        if !positive(self.rate) || self.burst.is_some_and(|burst| !positive(burst)) {
+            bail!("Throttling rate and burst must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// Open transactions are rolled back once they expire, like the Data API does
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransactionsConfig {
+    /// Roll back transactions without any call for this long
+    pub idle_timeout_ms: u64,
+    /// Roll back transactions started this long ago, even if still in use
+    pub max_lifetime_ms: u64,
+}
+
+impl Default for TransactionsConfig {
+    /// The limits of the Data API: 3 minutes without a call, 24 hours in total
+    fn default() -> Self {
+        Self {
+            idle_timeout_ms: 180_000,
+            max_lifetime_ms: 86_400_000,
+        }
+    }
+}
+
+/// Retries of the connections to the databases at startup, with an exponential backoff
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WaitConfig {
+    /// Give up and exit after this long, 0 to try only once
+    pub timeout_ms: u64,
+    /// Delay before the first retry, doubled after each attempt
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for WaitConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 60_000,
+            initial_backoff_ms: 250,
+            max_backoff_ms: 5_000,
+        }
+    }
+}
+
+impl WaitConfig {
+    fn validate(&self) -> Result<()> {
+        if self.initial_backoff_ms == 0 || self.initial_backoff_ms > self.max_backoff_ms {
+            bail!(
+                "wait_for_databases initial_backoff_ms ({}) must be positive and at most max_backoff_ms ({})",
+                self.initial_backoff_ms,
+                self.max_backoff_ms
+            );
+        }
+        Ok(())
+    }
+}
+
+/// An HTTPS listener, with a PEM certificate and private key
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// Port serving HTTPS alongside HTTP on the main `port`. Without it, the main `port`
+    /// serves HTTPS instead of HTTP.
+    pub port: Option<u16>,
+    /// PEM certificate chain, starting with the server's certificate
+    pub certificate_path: PathBuf,
+    /// PEM private key of the certificate
+    pub key_path: PathBuf,
+    /// Generate a self-signed certificate and its key into `certificate_path` and `key_path`
+    /// if they don't exist, for clients to trust
+    #[serde(default)]
+    pub self_signed: bool,
+    /// Host names and IP addresses the self-signed certificate is valid for
+    #[serde(default = "default_tls_hostnames")]
+    pub hostnames: Vec<String>,
+}
+
+fn default_tls_hostnames() -> Vec<String> {
+    ["localhost", "127.0.0.1", "::1"]
+        .map(str::to_string)
+        .to_vec()
+}
+
+impl TlsConfig {
+    fn validate(&self, http_port: u16) -> Result<()> {
+        if self.port == Some(http_port) {
+            bail!("TLS port {http_port} is already the HTTP port");
+        }
+        if self.self_signed {
+            if self.hostnames.is_empty() {
+                bail!("A self-signed certificate needs at least one of hostnames");
+            }
+            return Ok(());
+        }
+        for path in [&self.certificate_path, &self.key_path] {
+            if !path.is_file() {
+                bail!(
+                    "TLS file {} doesn't exist, set self_signed = true to generate it",
+                    path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How replayed requests are matched against the recorded ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayMatch {
+    /// Same SQL and parameters, byte for byte
+    Exact,
+    /// Same SQL once whitespace and comments are collapsed and literals replaced with `?`,
+    /// and same parameter names and values in any order
+    #[default]
+    Normalized,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3000,
+            ready_timeout_ms: 2000,
+            record_path: None,
+            replay_path: None,
+            replay_match: ReplayMatch::default(),
+            database_url: None,
+            clusters: vec![],
+            faults: vec![],
+            throttling: ThrottlingConfig::default(),
+            fixtures: vec![],
+            ephemeral_ttl_ms: 3_600_000,
+            transactions: TransactionsConfig::default(),
+            statement_log_size: 1000,
+            shutdown_timeout_ms: 10_000,
+            tls: None,
+            pool: PoolConfig::default(),
+            wait_for_databases: None,
+        }
+    }
+}
+
+impl Config {
+    /// Read the configuration file at `path`, or the one named by `RDS_LITE_CONFIG` if `None`,
+    /// then apply the overrides from the environment. Without a file, the configuration
+    /// comes from the environment alone.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let config = Self::read(path)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Like [`Config::load`], without validating the result, so that more overrides can be
+    /// applied first
+    pub fn read(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => Some(path.to_path_buf()),
+            None => std::env::var_os(CONFIG_ENV_VAR).map(PathBuf::from),
+        };
+
+        let mut table = match &path {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config file {}", path.display()))?;
+                toml::from_str::<toml::Table>(&contents)
+                    .with_context(|| format!("Invalid config file {}", path.display()))?
+            }
+            None => toml::Table::new(),
+        };
+
+        apply_env_overrides(&mut table, std::env::vars())?;
+
+        let config: Config = toml::Value::Table(table)
+            .try_into()
+            .with_context(|| match &path {
+                Some(path) => format!("Invalid config file {}", path.display()),
+                None => "Invalid configuration".to_string(),
+            })?;
+        Ok(config)
+    }
+
+    /// All the configured clusters, including the one defined by `database_url`, with the
+    /// top-level `pool` for the ones without a pool of their own
+    pub fn all_clusters(&self) -> Vec<ClusterConfig> {
+        let default = self.database_url.iter().map(|url| ClusterConfig {
+            name: "default".to_string(),
+            resource_arn: None,
+            database_url: url.clone(),
+            auto_pause_after_ms: None,
+            resume_time_ms: default_resume_time_ms(),
+            pool: None,
+        });
+        default
+            .chain(self.clusters.iter().cloned())
+            .map(|cluster| ClusterConfig {
+                pool: Some(cluster.pool.unwrap_or(self.pool)),
+                ..cluster
+            })
+            .collect()
+    }
+
+    /// Check the configuration makes sense, so that mistakes are reported at startup
+    pub fn validate(&self) -> Result<()> {
+        let clusters = self.all_clusters();
+        if clusters.is_empty() && self.replay_path.is_none() {
+            bail!(
+                "No database configured: set DATABASE_URL, add a [[clusters]] section or set replay_path"
+            );
+        }
+
+        let mut names = HashSet::new();
+        let mut arns = HashSet::new();
+        let mut default_cluster = None;
+        for cluster in &clusters {
+            if !names.insert(cluster.name.as_str()) {
+                bail!("Cluster name '{}' is used more than once", cluster.name);
+            }
+            match &cluster.resource_arn {
+                Some(arn) if !arns.insert(arn.as_str()) => {
+                    bail!("Resource ARN '{arn}' is used by more than one cluster")
+                }
+                Some(_) => {}
+                None => {
+                    if let Some(other) = default_cluster.replace(&cluster.name) {
+                        bail!(
+                            "Clusters '{other}' and '{}' both have no resource_arn, only one cluster can be the default",
+                            cluster.name
+                        );
+                    }
+                }
+            }
+            crate::backend::validate_url(&cluster.database_url)
+                .with_context(|| format!("Invalid database_url for cluster '{}'", cluster.name))?;
+            if let Some(pool) = &cluster.pool {
+                pool.validate()
+                    .with_context(|| format!("Invalid pool for cluster '{}'", cluster.name))?;
+            }
+        }
+
+        for fault in &self.faults {
+            fault.validate()?;
+        }
+        let throttling = &self.throttling;
+        let overrides = throttling.overrides.iter().map(RateLimitOverride::limit);
+        for limit in [
+            throttling.global,
+            throttling.per_secret,
+            throttling.per_resource,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(overrides)
+        {
+            limit.validate()?;
+        }
+        if self.transactions.idle_timeout_ms == 0 || self.transactions.max_lifetime_ms == 0 {
+            bail!("Transaction idle_timeout_ms and max_lifetime_ms must be positive");
+        }
+        if let Some(tls) = &self.tls {
+            tls.validate(self.port)?;
+        }
+        if let Some(wait) = &self.wait_for_databases {
+            wait.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Override keys of `table` with the `RDS_LITE_*` variables in `vars`, plus the
+/// `PORT` and `DATABASE_URL` variables rds-lite has always supported. Variables naming no
+/// key of `Config` are skipped with a warning.
+fn apply_env_overrides(
+    table: &mut toml::Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<()> {
+    for (name, value) in vars {
+        let key = match name.as_str() {
+            "PORT" => "port".to_string(),
+            "DATABASE_URL" => "database_url".to_string(),
+            CONFIG_ENV_VAR => continue,
+            _ => match name.strip_prefix(ENV_OVERRIDE_PREFIX) {
+                Some(key) => key.to_ascii_lowercase(),
+                None => continue,
+            },
+        };
+        let top_level = key.split("__").next().unwrap_or_default();
+        if !CONFIG_KEYS.contains(&top_level) {
+            warn!("Ignoring {name}, as rds-lite has no '{top_level}' setting");
+            continue;
+        }
+
+        // Values are parsed as TOML when possible, so that numbers and booleans keep their
+        // type, unless the key expects a string, e.g. `RDS_LITE_RECORD_PATH=123`
+        let string = toml::Value::String(value.clone());
+        let value = match toml::from_str::<toml::Table>(&format!("value = {value}"))
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+        {
+            Some(parsed)
+                if parsed.is_str()
+                    || has_expected_type(&key, parsed.clone())
+                    || !has_expected_type(&key, string.clone()) =>
+            {
+                parsed
+            }
+            _ => string,
+        };
+        set_key(table, &key, value)
+            .with_context(|| format!("Cannot override {key} with {name}"))?;
+    }
+    Ok(())
+}
+
+/// Set `key` of `table`, with `__` separating the keys of nested tables
+fn set_key(table: &mut toml::Table, key: &str, value: toml::Value) -> Result<()> {
+    let mut path = key.split("__").peekable();
+    let mut current = table;
+    while let Some(segment) = path.next() {
+        if path.peek().is_none() {
+            current.insert(segment.to_string(), value);
+            break;
+        }
+        let entry = current
+            .entry(segment.to_string())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        let Some(next) = entry.as_table_mut() else {
+            bail!("'{segment}' is not a table");
+        };
+        current = next;
+    }
+    Ok(())
+}
+
+/// Whether `Config` accepts a value of the type of `value` for `key`, checked on a
+/// configuration made of it alone, where only a type mismatch counts, not missing keys
+fn has_expected_type(key: &str, value: toml::Value) -> bool {
+    let mut table = toml::Table::new();
+    if set_key(&mut table, key, value).is_err() {
+        return false;
+    }
+    match toml::Value::Table(table).try_into::<Config>() {
+        Ok(_) => true,
+        Err(e) => !e.to_string().contains("invalid type"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str, vars: &[(&str, &str)]) -> Result<Config> {
+        let mut table = toml::from_str::<toml::Table>(contents)?;
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        apply_env_overrides(&mut table, vars)?;
+        let config: Config = toml::Value::Table(table).try_into()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn test_clusters() {
+        let config = parse(
+            r#"
+            port = 4000
+
+            [[clusters]]
+            name = "orders"
+            resource_arn = "arn:aws:rds:eu-west-1:123456789012:cluster:orders"
+            database_url = "postgres://localhost/orders"
+
+            [[clusters]]
+            name = "fallback"
+            database_url = "mysql://localhost"
+            "#,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.port, 4000);
+        assert_eq!(config.bind_address, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        assert_eq!(config.all_clusters().len(), 2);
+    }
+
+    #[test]
+    fn test_pools() {
+        let config = parse(
+            r#"
+            database_url = "postgres://localhost/app"
+
+            [pool]
+            max_connections = 20
+
+            [[clusters]]
+            name = "reports"
+            resource_arn = "arn:aws:rds:eu-west-1:123456789012:cluster:reports"
+            database_url = "postgres://localhost/reports"
+
+            [clusters.pool]
+            max_connections = 2
+            acquire_timeout_ms = 500
+            statement_cache_capacity = 0
+            "#,
+            &[],
+        )
+        .unwrap();
+        let clusters = config.all_clusters();
+        let default = clusters[0].pool.unwrap();
+        assert_eq!(default.max_connections, 20);
+        assert_eq!(default.acquire_timeout_ms, 30_000);
+        let reports = clusters[1].pool.unwrap();
+        assert_eq!(reports.max_connections, 2);
+        assert_eq!(reports.acquire_timeout_ms, 500);
+        assert_eq!(reports.idle_timeout_ms, 600_000);
+        assert_eq!(default.statement_cache_capacity, 100);
+        assert_eq!(reports.statement_cache_capacity, 0);
+
+        let invalid = "database_url = \"sqlite::memory:\"\n[pool]\nmin_connections = 11";
+        assert!(parse(invalid, &[]).is_err());
+    }
+
+    #[test]
+    fn test_config_keys() {
+        // Lists the fields of `Config` in its unknown field error
+        let error = toml::from_str::<Config>("not_a_key = 1").unwrap_err();
+        let expected = CONFIG_KEYS
+            .iter()
+            .map(|key| format!("`{key}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        assert!(error.to_string().contains(&expected), "{error}");
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let config = parse(
+            r#"
+            port = 4000
+            database_url = "mysql://localhost"
+            "#,
+            &[
+                ("RDS_LITE_PORT", "5000"),
+                ("RDS_LITE_BIND_ADDRESS", "::1"),
+                ("DATABASE_URL", "sqlite::memory:"),
+                ("RDS_LITE_WAIT_FOR_DATABASES__TIMEOUT_MS", "30000"),
+                ("RDS_LITE_VERSION", "1.2.3"),
+                ("HOME", "/root"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.port, 5000);
+        assert_eq!(config.bind_address, "::1".parse::<IpAddr>().unwrap());
+        assert_eq!(config.database_url.as_deref(), Some("sqlite::memory:"));
+        assert_eq!(
+            config.wait_for_databases,
+            Some(WaitConfig {
+                timeout_ms: 30_000,
+                ..WaitConfig::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_env_overrides_of_strings() {
+        let database_url = ("DATABASE_URL", "sqlite::memory:");
+        // Values that look like numbers or booleans stay strings for the keys expecting one
+        let config = parse("", &[database_url, ("RDS_LITE_RECORD_PATH", "123")]).unwrap();
+        assert_eq!(config.record_path, Some(PathBuf::from("123")));
+        let config = parse("", &[database_url, ("RDS_LITE_RECORD_PATH", "true")]).unwrap();
+        assert_eq!(config.record_path, Some(PathBuf::from("true")));
+
+        // The other keys still get numbers, even in a table missing other keys for now,
+        // and reject strings
+        let err = parse("", &[database_url, ("RDS_LITE_TLS__PORT", "8443")]).unwrap_err();
+        assert!(format!("{err:#}").contains("missing field"), "{err:#}");
+        let err = parse("", &[database_url, ("RDS_LITE_PORT", "true")]).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("invalid type: boolean `true`"),
+            "{err:#}"
+        );
+        let err = parse("", &[database_url, ("RDS_LITE_PORT", "http")]).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("invalid type: string"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        let err = parse("", &[]).unwrap_err();
+        assert!(err.to_string().contains("No database configured"));
+
+        let err = parse(r#"database_url = "oracle://localhost""#, &[]).unwrap_err();
+        assert!(format!("{err:#}").contains("Unsupported database URL scheme 'oracle'"));
+
+        let err = parse(
+            r#"
+            database_url = "mysql://localhost"
+            [[clusters]]
+            name = "other"
+            database_url = "mysql://localhost"
+            "#,
+            &[],
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("only one cluster can be the default")
+        );
+
+        let err = parse(
+            r#"
+            database_url = "sqlite::memory:"
+            [[faults]]
+            sql = "(unclosed"
+            inject = { type = "latency", latency_ms = 10 }
+            "#,
+            &[],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Invalid sql regex"));
+
+        let err = parse(
+            r#"
+            database_url = "sqlite::memory:"
+            [tls]
+            certificate_path = "/nonexistent/cert.pem"
+            key_path = "/nonexistent/key.pem"
+            "#,
+            &[],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("set self_signed = true"));
+
+        let err = parse(
+            r#"
+            database_url = "sqlite::memory:"
+            [wait_for_databases]
+            initial_backoff_ms = 10000
+            "#,
+            &[],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("at most max_backoff_ms"));
+
+        let err = parse(r#"prot = 3000"#, &[]).unwrap_err();
+        assert!(err.to_string().contains("unknown field `prot`"));
+
+        let err = parse(r#"port = "many""#, &[]).unwrap_err();
+        assert!(err.to_string().contains("invalid type"));
+    }
+}
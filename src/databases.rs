@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::cluster::{Cluster, Clusters};
+
+/// A database cloned for a single user, e.g. a test, dropped when released or expired
+struct Ephemeral {
+    cluster: Arc<Cluster>,
+    template: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// The clusters, and the ephemeral databases cloned on them
+pub(crate) struct Databases {
+    clusters: Arc<Clusters>,
+    default_ttl: Duration,
+    /// Ephemeral databases by cluster and database name
+    ephemerals: Mutex<HashMap<(String, String), Ephemeral>>,
+}
+
+impl Databases {
+    pub(crate) fn new(clusters: Arc<Clusters>, default_ttl: Duration) -> Self {
+        Self {
+            clusters,
+            default_ttl,
+            ephemerals: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn find_cluster(&self, query: &DatabaseQuery) -> Result<Arc<Cluster>, (StatusCode, String)> {
+        match &query.cluster {
+            Some(name) => self.clusters.named(name),
+            None => self.clusters.get(None),
+        }
+        .cloned()
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!(
+                    "No cluster '{}'",
+                    query.cluster.as_deref().unwrap_or("default")
+                ),
+            )
+        })
+    }
+
+    /// Drop the ephemeral `database` of `cluster`, returning whether there was one. It's only
+    /// forgotten once dropped, so that a failed drop can be retried.
+    async fn release(&self, cluster: &str, database: &str) -> anyhow::Result<bool> {
+        let key = (cluster.to_string(), database.to_string());
+        let Some(owner) = self
+            .ephemerals
+            .lock()
+            .unwrap()
+            .get(&key)
+            .map(|ephemeral| ephemeral.cluster.clone())
+        else {
+            return Ok(false);
+        };
+        owner.backend.drop_database(database).await?;
+        self.ephemerals.lock().unwrap().remove(&key);
+        info!("Dropped ephemeral database '{database}' of cluster '{cluster}'");
+        Ok(true)
+    }
+
+    /// Drop the ephemeral databases still alive, when the server shuts down
+    pub(crate) async fn release_all(&self) {
+        let keys: Vec<(String, String)> = self.ephemerals.lock().unwrap().keys().cloned().collect();
+        for (cluster, database) in keys {
+            if let Err(e) = self.release(&cluster, &database).await {
+                error!("Failed to drop ephemeral database '{database}': {e:#}");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DatabaseQuery {
+    /// Name of the cluster holding the database, the default one if unset
+    cluster: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloneQuery {
+    cluster: Option<String>,
+    /// Lifetime of the clone, the configured `ephemeral_ttl_ms` if unset
+    ttl_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct EphemeralDef {
+    cluster: String,
+    database: String,
+    template: String,
+    expires_at: DateTime<Utc>,
+}
+
+async fn snapshot_database(
+    State(databases): State<Arc<Databases>>,
+    Path(database): Path<String>,
+    Query(query): Query<DatabaseQuery>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let cluster = databases.find_cluster(&query)?;
+    cluster
+        .backend
+        .snapshot_database(&database)
+        .await
+        .map_err(|e| {
+            error!("Failed to snapshot database '{database}': {e:#}");
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}"))
+        })?;
+    info!(
+        "Saved a snapshot of database '{database}' of cluster '{}'",
+        cluster.name
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn reset_database(
+    State(databases): State<Arc<Databases>>,
+    Path(database): Path<String>,
+    Query(query): Query<DatabaseQuery>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let cluster = databases.find_cluster(&query)?;
+    cluster
+        .backend
+        .restore_database(&database)
+        .await
+        .map_err(|e| {
+            error!("Failed to reset database '{database}': {e:#}");
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}"))
+        })?;
+    info!("Reset database '{database}' of cluster '{}'", cluster.name);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn clone_database(
+    State(databases): State<Arc<Databases>>,
+    Path(template): Path<String>,
+    Query(query): Query<CloneQuery>,
+) -> Result<(StatusCode, Json<EphemeralDef>), (StatusCode, String)> {
+    let cluster = databases.find_cluster(&DatabaseQuery {
+        cluster: query.cluster,
+    })?;
+    let ttl = query
+        .ttl_ms
+        .map(Duration::from_millis)
+        .unwrap_or(databases.default_ttl);
+    // Checked before cloning, so that a clone is never left without an expiry
+    let expires_at = chrono::TimeDelta::from_std(ttl)
+        .ok()
+        .and_then(|ttl| Utc::now().checked_add_signed(ttl))
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("ttl_ms {} is out of range", ttl.as_millis()),
+            )
+        })?;
+    let database = format!("{template}_{:08x}", rand::random::<u32>());
+
+    cluster
+        .backend
+        .clone_database(&template, &database)
+        .await
+        .map_err(|e| {
+            error!("Failed to clone database '{template}': {e:#}");
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}"))
+        })?;
+    info!(
+        "Cloned database '{template}' of cluster '{}' into '{database}', until {expires_at}",
+        cluster.name
+    );
+
+    let key = (cluster.name.clone(), database.clone());
+    databases.ephemerals.lock().unwrap().insert(
+        key.clone(),
+        Ephemeral {
+            cluster: cluster.clone(),
+            template: template.clone(),
+            expires_at,
+        },
+    );
+    // Nothing is left to do if the database was released in the meantime
+    let expiring = databases.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(ttl).await;
+        if let Err(e) = expiring.release(&key.0, &key.1).await {
+            error!("Failed to drop expired database '{}': {e:#}", key.1);
+        }
+    });
+
+    Ok((
+        StatusCode::CREATED,
+        Json(EphemeralDef {
+            cluster: cluster.name.clone(),
+            database,
+            template,
+            expires_at,
+        }),
+    ))
+}
+
+async fn release_database(
+    State(databases): State<Arc<Databases>>,
+    Path(database): Path<String>,
+    Query(query): Query<DatabaseQuery>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let cluster = databases.find_cluster(&query)?;
+    match databases.release(&cluster.name, &database).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            format!("No ephemeral database '{database}'"),
+        )),
+        Err(e) => {
+            error!("Failed to drop database '{database}': {e:#}");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))
+        }
+    }
+}
+
+async fn list_ephemerals(State(databases): State<Arc<Databases>>) -> Json<Vec<EphemeralDef>> {
+    let mut ephemerals: Vec<EphemeralDef> = databases
+        .ephemerals
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((cluster, database), ephemeral)| EphemeralDef {
+            cluster: cluster.clone(),
+            database: database.clone(),
+            template: ephemeral.template.clone(),
+            expires_at: ephemeral.expires_at,
+        })
+        .collect();
+    ephemerals.sort_by_key(|ephemeral| ephemeral.expires_at);
+    Json(ephemerals)
+}
+
+/// `/_admin/databases` endpoints saving databases, bringing them back to their snapshot,
+/// and cloning them into ephemeral databases
+pub(crate) fn admin_router(databases: Arc<Databases>) -> Router {
+    Router::new()
+        .route("/_admin/databases", get(list_ephemerals))
+        .route("/_admin/databases/{name}", delete(release_database))
+        .route("/_admin/databases/{name}/snapshot", post(snapshot_database))
+        .route("/_admin/databases/{name}/reset", post(reset_database))
+        .route("/_admin/databases/{name}/clone", post(clone_database))
+        .with_state(databases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    use crate::Builder;
+    use crate::backend::{MockBackend, SqliteBackend};
+    use crate::test_util::call;
+
+    #[tokio::test]
+    async fn test_snapshot_and_reset() {
+        let app = Builder::new()
+            .backend(SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+        let execute = |sql: &str| json!({"database": "app", "sql": sql});
+
+        let (status, body) = call(&app, "POST", "/_admin/databases/app/reset", Value::Null).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body, json!("No snapshot of database 'app'"));
+
+        for sql in [
+            "CREATE TABLE t (id INTEGER PRIMARY KEY AUTOINCREMENT, x TEXT)",
+            "CREATE INDEX t_x ON t (x)",
+            "INSERT INTO t (x) VALUES ('seeded')",
+        ] {
+            let (status, body) = call(&app, "POST", "/Execute", execute(sql)).await;
+            assert_eq!(status, StatusCode::OK, "{body}");
+        }
+        let (status, _) = call(&app, "POST", "/_admin/databases/app/snapshot", Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        for sql in [
+            "INSERT INTO t (x) VALUES ('added')",
+            "DELETE FROM t WHERE x = 'seeded'",
+            "CREATE TABLE other (y INTEGER)",
+        ] {
+            call(&app, "POST", "/Execute", execute(sql)).await;
+        }
+
+        let (status, body) = call(&app, "POST", "/_admin/databases/app/reset", Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT, "{body}");
+
+        let (_, body) = call(&app, "POST", "/Execute", execute("SELECT id, x FROM t")).await;
+        assert_eq!(
+            body["records"],
+            json!([[{"longValue": 1}, {"stringValue": "seeded"}]])
+        );
+        let (status, _) = call(&app, "POST", "/Execute", execute("SELECT * FROM other")).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        // The AUTOINCREMENT counter is restored too
+        call(
+            &app,
+            "POST",
+            "/Execute",
+            execute("INSERT INTO t (x) VALUES ('new')"),
+        )
+        .await;
+        let (_, body) = call(&app, "POST", "/Execute", execute("SELECT max(id) FROM t")).await;
+        assert_eq!(body["records"], json!([[{"longValue": 2}]]));
+
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/_admin/databases/app/reset?cluster=missing",
+            Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_clone_and_release() {
+        let app = Builder::new()
+            .backend(SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+        for sql in [
+            "CREATE TABLE t (x TEXT)",
+            "INSERT INTO t VALUES ('template')",
+        ] {
+            let body = json!({"database": "app", "sql": sql});
+            call(&app, "POST", "/Execute", body).await;
+        }
+
+        let (status, body) = call(&app, "POST", "/_admin/databases/app/clone", Value::Null).await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        let clone = body["database"].as_str().unwrap().to_string();
+        assert!(clone.starts_with("app_"), "{clone}");
+
+        let execute = |sql: &str| json!({"database": clone, "sql": sql});
+        call(
+            &app,
+            "POST",
+            "/Execute",
+            execute("INSERT INTO t VALUES ('clone')"),
+        )
+        .await;
+        let (_, body) = call(&app, "POST", "/Execute", execute("SELECT count(*) FROM t")).await;
+        assert_eq!(body["records"], json!([[{"longValue": 2}]]));
+        let template = json!({"database": "app", "sql": "SELECT count(*) FROM t"});
+        let (_, body) = call(&app, "POST", "/Execute", template).await;
+        assert_eq!(body["records"], json!([[{"longValue": 1}]]));
+
+        let (_, body) = call(&app, "GET", "/_admin/databases", Value::Null).await;
+        assert_eq!(body[0]["database"], json!(clone));
+        assert_eq!(body[0]["template"], "app");
+
+        let uri = format!("/_admin/databases/{clone}");
+        let (status, _) = call(&app, "DELETE", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = call(&app, "DELETE", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = call(&app, "POST", "/Execute", execute("SELECT * FROM t")).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        // Clones expire on their own
+        let uri = "/_admin/databases/app/clone?ttl_ms=50";
+        let (status, _) = call(&app, "POST", uri, Value::Null).await;
+        assert_eq!(status, StatusCode::CREATED);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let (_, body) = call(&app, "GET", "/_admin/databases", Value::Null).await;
+        assert_eq!(body, json!([]));
+
+        let uri = "/_admin/databases/app/clone?ttl_ms=18446744073709551615";
+        let (status, body) = call(&app, "POST", uri, Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, json!("ttl_ms 18446744073709551615 is out of range"));
+        let (_, body) = call(&app, "GET", "/_admin/databases", Value::Null).await;
+        assert_eq!(body, json!([]));
+
+        let (status, body) =
+            call(&app, "POST", "/_admin/databases/missing/clone", Value::Null).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body, json!("Unknown database 'missing'"));
+    }
+
+    #[tokio::test]
+    async fn test_release() {
+        let mut clusters = Clusters::default();
+        clusters.add(Cluster {
+            name: "default".to_string(),
+            resource_arn: None,
+            backend: Arc::new(SqliteBackend::in_memory()),
+        });
+        clusters.add(Cluster {
+            name: "mock".to_string(),
+            resource_arn: Some("arn:mock".to_string()),
+            backend: Arc::new(MockBackend::from_toml("").unwrap()),
+        });
+        let clusters = Arc::new(clusters);
+        let databases = Databases::new(clusters.clone(), Duration::from_secs(60));
+        for (cluster, database) in [("default", "app_1"), ("mock", "app_2")] {
+            databases.ephemerals.lock().unwrap().insert(
+                (cluster.to_string(), database.to_string()),
+                Ephemeral {
+                    cluster: clusters.named(cluster).unwrap().clone(),
+                    template: "app".to_string(),
+                    expires_at: Utc::now(),
+                },
+            );
+        }
+
+        // The mock backend can't drop databases, so its clone is kept to be released again
+        databases.release_all().await;
+        let ephemerals = databases.ephemerals.lock().unwrap();
+        let left: Vec<_> = ephemerals.keys().collect();
+        assert_eq!(left, [&("mock".to_string(), "app_2".to_string())]);
+    }
+}
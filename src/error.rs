@@ -0,0 +1,73 @@
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+
+use crate::query::QueryError;
+
+/// Header AWS uses to tell clients the type of an error, `x-amzn-ErrorType`
+pub const ERROR_TYPE_HEADER: &str = "x-amzn-errortype";
+
+/// An error answered the way the Data API does, so that the AWS SDKs recognize its type
+#[derive(Debug, Clone)]
+pub struct AwsError {
+    pub status: StatusCode,
+    /// e.g. `BadRequestException`
+    pub error_type: String,
+    pub message: String,
+}
+
+impl AwsError {
+    /// An error with the HTTP status the Data API uses for `error_type`
+    pub fn new(error_type: &str, message: impl Into<String>) -> Self {
+        Self {
+            status: default_status(error_type),
+            error_type: error_type.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// HTTP status of the Data API errors, 400 for the ones not listed
+pub fn default_status(error_type: &str) -> StatusCode {
+    match error_type {
+        "AccessDeniedException" | "ForbiddenException" => StatusCode::FORBIDDEN,
+        "NotFoundException" | "TransactionNotFoundException" | "DatabaseNotFoundException" => {
+            StatusCode::NOT_FOUND
+        }
+        "ThrottlingException" => StatusCode::TOO_MANY_REQUESTS,
+        "InternalServerErrorException" => StatusCode::INTERNAL_SERVER_ERROR,
+        "ServiceUnavailableError" => StatusCode::SERVICE_UNAVAILABLE,
+        "DatabaseUnavailableException" => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
+
+impl std::fmt::Display for AwsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.error_type, self.message)
+    }
+}
+
+impl std::error::Error for AwsError {}
+
+/// The response to a failed statement, as an [`AwsError`] if it's one
+pub(crate) fn query_error_response((status, error): QueryError) -> Response {
+    match error.downcast::<AwsError>() {
+        Ok(error) => error.into_response(),
+        Err(error) => (status, error.to_string()).into_response(),
+    }
+}
+
+impl IntoResponse for AwsError {
+    fn into_response(self) -> Response {
+        (
+            self.status,
+            [(ERROR_TYPE_HEADER, self.error_type)],
+            Json(json!({ "message": self.message })),
+        )
+            .into_response()
+    }
+}
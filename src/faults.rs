@@ -0,0 +1,336 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{
+    Json, Router,
+    body::{Body, Bytes},
+    extract::{Path, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+};
+use log::info;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::api::{RequestFields, request_action, request_fields};
+use crate::config::{Fault, FaultConfig};
+use crate::error::AwsError;
+
+struct ActiveFault {
+    id: u64,
+    config: FaultConfig,
+    sql: Option<Regex>,
+    /// Matching requests seen so far
+    calls: u64,
+    injected: u64,
+}
+
+impl ActiveFault {
+    fn matches(&self, action: &str, request: &RequestFields) -> bool {
+        self.config.action.as_deref().is_none_or(|a| a == action)
+            && self
+                .sql
+                .as_ref()
+                .is_none_or(|sql| request.sql.as_deref().is_some_and(|s| sql.is_match(s)))
+            && self
+                .config
+                .database
+                .as_deref()
+                .is_none_or(|d| request.database.as_deref() == Some(d))
+            && self
+                .config
+                .secret_arn
+                .as_deref()
+                .is_none_or(|s| request.secret_arn.as_deref() == Some(s))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FaultDef {
+    id: u64,
+    calls: u64,
+    injected: u64,
+    #[serde(flatten)]
+    config: FaultConfig,
+}
+
+/// The fault rules, which can be changed while the server runs
+#[derive(Default)]
+pub struct Faults {
+    rules: Mutex<Vec<ActiveFault>>,
+    next_id: Mutex<u64>,
+}
+
+impl Faults {
+    pub fn new(configs: &[FaultConfig]) -> Result<Self> {
+        let faults = Self::default();
+        for config in configs {
+            faults.add(config.clone())?;
+        }
+        Ok(faults)
+    }
+
+    /// Add a rule, checked after the existing ones, and return its id
+    pub fn add(&self, config: FaultConfig) -> Result<u64> {
+        config.validate()?;
+        let sql = config.sql.as_deref().map(Regex::new).transpose()?;
+
+        let mut next_id = self.next_id.lock().unwrap();
+        *next_id += 1;
+        self.rules.lock().unwrap().push(ActiveFault {
+            id: *next_id,
+            config,
+            sql,
+            calls: 0,
+            injected: 0,
+        });
+        Ok(*next_id)
+    }
+
+    fn list(&self) -> Vec<FaultDef> {
+        self.rules
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|rule| FaultDef {
+                id: rule.id,
+                calls: rule.calls,
+                injected: rule.injected,
+                config: rule.config.clone(),
+            })
+            .collect()
+    }
+
+    /// The fault of the first rule that fires for `request`, if any
+    fn pick(&self, action: &str, request: &RequestFields) -> Option<Fault> {
+        let mut rules = self.rules.lock().unwrap();
+        for rule in rules.iter_mut() {
+            if !rule.matches(action, request) {
+                continue;
+            }
+            rule.calls += 1;
+            let fires = rule.config.on_call.is_none_or(|n| n == rule.calls)
+                && rand::random::<f64>() < rule.config.probability;
+            if fires {
+                rule.injected += 1;
+                info!("Injecting fault {} into {action} request", rule.id);
+                return Some(rule.config.inject.clone());
+            }
+        }
+        None
+    }
+}
+
+fn aws_error(error: String, message: Option<String>, status: Option<u16>) -> AwsError {
+    let mut aws_error = AwsError::new(
+        &error,
+        message.unwrap_or_else(|| format!("{error} injected by rds-lite")),
+    );
+    if let Some(status) = status.and_then(|s| StatusCode::from_u16(s).ok()) {
+        aws_error.status = status;
+    }
+    aws_error
+}
+
+/// Middleware injecting the faults of the first rule firing for each Data API request
+pub(crate) async fn inject(
+    State(faults): State<Arc<Faults>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if faults.rules.lock().unwrap().is_empty() {
+        return next.run(request).await;
+    }
+
+    let action = request_action(&request);
+    let fault = faults.pick(action, request_fields(&request));
+
+    match fault {
+        None => next.run(request).await,
+        Some(Fault::Error {
+            error,
+            message,
+            status,
+        }) => aws_error(error, message, status).into_response(),
+        Some(Fault::Latency { latency_ms }) => {
+            tokio::time::sleep(Duration::from_millis(latency_ms)).await;
+            next.run(request).await
+        }
+        Some(Fault::ConnectionReset) => {
+            // Failing the body once the headers are sent makes the server drop the connection
+            let body = futures_util::stream::once(async {
+                Err::<Bytes, _>(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "Connection reset injected by rds-lite",
+                ))
+            });
+            Response::new(Body::from_stream(body))
+        }
+        Some(Fault::PartialFailure {
+            error,
+            message,
+            status,
+        }) => {
+            // A transaction begun but never returned would hold its connection until it
+            // expires, so BeginTransaction fails without beginning one
+            if action != "BeginTransaction" {
+                next.run(request).await;
+            }
+            aws_error(error, message, status).into_response()
+        }
+    }
+}
+
+async fn list_faults(State(faults): State<Arc<Faults>>) -> Json<Vec<FaultDef>> {
+    Json(faults.list())
+}
+
+async fn add_fault(
+    State(faults): State<Arc<Faults>>,
+    Json(config): Json<FaultConfig>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, String)> {
+    let id = faults
+        .add(config)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("{e:#}")))?;
+    Ok((StatusCode::CREATED, Json(serde_json::json!({ "id": id }))))
+}
+
+async fn delete_fault(State(faults): State<Arc<Faults>>, Path(id): Path<u64>) -> StatusCode {
+    let mut rules = faults.rules.lock().unwrap();
+    let count = rules.len();
+    rules.retain(|rule| rule.id != id);
+    if rules.len() < count {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn delete_all_faults(State(faults): State<Arc<Faults>>) -> StatusCode {
+    faults.rules.lock().unwrap().clear();
+    StatusCode::NO_CONTENT
+}
+
+/// `/_admin/faults` endpoints listing, adding and removing fault rules
+pub(crate) fn admin_router(faults: Arc<Faults>) -> Router {
+    Router::new()
+        .route(
+            "/_admin/faults",
+            get(list_faults).post(add_fault).delete(delete_all_faults),
+        )
+        .route("/_admin/faults/{id}", delete(delete_fault))
+        .with_state(faults)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::Builder;
+    use crate::backend::SqliteBackend;
+    use crate::test_util::call;
+
+    #[tokio::test]
+    async fn test_faults() {
+        let app = Builder::new()
+            .backend(SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+        call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"sql": "CREATE TABLE t (x INTEGER)"}),
+        )
+        .await;
+
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/_admin/faults",
+            json!({
+                "action": "ExecuteStatement",
+                "sql": "(?i)^select",
+                "on_call": 2,
+                "inject": {"type": "error", "error": "StatementTimeoutException"},
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let timeout_id = body["id"].as_u64().unwrap();
+
+        // Only the second matching request fails
+        for expected in [StatusCode::OK, StatusCode::BAD_REQUEST, StatusCode::OK] {
+            let (status, body) = call(&app, "POST", "/Execute", json!({"sql": "SELECT 1"})).await;
+            assert_eq!(status, expected, "{body}");
+            if status != StatusCode::OK {
+                assert_eq!(body["type"], "StatementTimeoutException");
+            }
+        }
+
+        // A partial failure runs the statement but answers an error
+        call(
+            &app,
+            "POST",
+            "/_admin/faults",
+            json!({
+                "sql": "INSERT",
+                "inject": {"type": "partial_failure", "error": "ServiceUnavailableError"},
+            }),
+        )
+        .await;
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"sql": "INSERT INTO t VALUES (1)"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["type"], "ServiceUnavailableError");
+
+        // but doesn't begin a transaction it wouldn't return
+        call(
+            &app,
+            "POST",
+            "/_admin/faults",
+            json!({
+                "action": "BeginTransaction",
+                "inject": {"type": "partial_failure", "error": "ServiceUnavailableError"},
+            }),
+        )
+        .await;
+        let (status, _) = call(&app, "POST", "/BeginTransaction", json!({})).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        let (_, transactions) = call(&app, "GET", "/_admin/transactions", Value::Null).await;
+        assert_eq!(transactions, json!([]));
+
+        let (_, faults) = call(&app, "GET", "/_admin/faults", Value::Null).await;
+        assert_eq!(faults[0]["calls"], 3);
+        assert_eq!(faults[0]["injected"], 1);
+        assert_eq!(faults[1]["injected"], 1);
+        assert_eq!(faults[2]["injected"], 1);
+
+        let uri = format!("/_admin/faults/{timeout_id}");
+        let (status, _) = call(&app, "DELETE", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = call(&app, "DELETE", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        call(&app, "DELETE", "/_admin/faults", Value::Null).await;
+
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"sql": "SELECT count(*) FROM t"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["records"], json!([[{"longValue": 1}]]));
+    }
+}
@@ -0,0 +1,365 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use log::{info, warn};
+use sqlx::Either;
+
+use crate::aws::FieldDef;
+use crate::cluster::{Cluster, Clusters};
+use crate::config::FixturesConfig;
+use crate::query::{Dialect, TokenKind, tokenize};
+
+/// Table recording, in each seeded database, the fixture files already applied
+const APPLIED_FIXTURES_TABLE: &str = "rds_lite_fixtures";
+
+/// A statement of a script, with the line it starts on
+#[derive(Debug, PartialEq, Eq)]
+struct Statement {
+    line: usize,
+    sql: String,
+}
+
+/// Split a script into statements separated by `;`, ignoring the ones in quotes and comments,
+/// with the same tokenizer as the named parameter rewriting
+fn split_statements(script: &str, dialect: Dialect) -> Vec<Statement> {
+    let mut statements = vec![];
+    let mut current = String::new();
+    // Line of the first token of the current statement that isn't blank or a comment
+    let mut start_line = None;
+    let mut line = 1;
+
+    for token in tokenize(script, dialect) {
+        let token_line = line;
+        line += token.text.matches('\n').count();
+
+        if token.kind == TokenKind::Other && token.text == ";" {
+            if let Some(line) = start_line.take() {
+                statements.push(Statement {
+                    line,
+                    sql: current.trim().to_string(),
+                });
+            }
+            current.clear();
+            continue;
+        }
+        current.push_str(token.text);
+        if token.kind != TokenKind::Comment && !token.text.trim().is_empty() {
+            start_line.get_or_insert(token_line);
+        }
+    }
+
+    if let Some(line) = start_line {
+        statements.push(Statement {
+            line,
+            sql: current.trim().to_string(),
+        });
+    }
+    statements
+}
+
+/// The fixture files already run on `database`
+async fn applied_fixtures(cluster: &Cluster, database: &str) -> Result<Vec<String>> {
+    let backend = &cluster.backend;
+    backend
+        .execute_raw(
+            database,
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {APPLIED_FIXTURES_TABLE} (
+                    file_name VARCHAR(255) NOT NULL PRIMARY KEY,
+                    applied_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+                )"
+            ),
+        )
+        .await?;
+
+    let sql = format!("SELECT file_name FROM {APPLIED_FIXTURES_TABLE}");
+    let output = backend
+        .run_query(Some(database), None, &sql, &[], vec![vec![]])
+        .await
+        .map_err(|(_, e)| e)?;
+    let Either::Left(records) = output else {
+        return Ok(vec![]);
+    };
+    Ok(records
+        .rows
+        .into_iter()
+        .filter_map(|record| match record.into_iter().next() {
+            Some(FieldDef::StringValue(file_name)) => Some(file_name),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Run the fixture files of `fixtures` that haven't been run yet
+async fn seed_database(cluster: &Cluster, fixtures: &FixturesConfig) -> Result<()> {
+    let database = fixtures.database.as_str();
+    let directory = &fixtures.directory;
+
+    let mut files = vec![];
+    for entry in std::fs::read_dir(directory)
+        .with_context(|| format!("Failed to read fixtures directory {}", directory.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "sql") && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    cluster.backend.create_database(database).await?;
+    let applied = applied_fixtures(cluster, database).await?;
+
+    for path in files {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("Invalid fixture file name {}", path.display()))?;
+        if applied.iter().any(|applied| applied == file_name) {
+            continue;
+        }
+
+        info!(
+            "Applying fixture {} to database '{database}'",
+            path.display()
+        );
+        apply_fixture(cluster, database, &path, file_name).await?;
+    }
+
+    // The fixtures are applied, so a failed snapshot only leaves the database without one
+    if fixtures.snapshot
+        && let Err(e) = cluster.backend.snapshot_database(database).await
+    {
+        warn!("Failed to snapshot seeded database '{database}': {e:#}");
+    }
+    Ok(())
+}
+
+/// Run the fixture file at `path` and record it as applied, in one transaction so that a file
+/// failing halfway leaves nothing behind. MySQL commits DDL statements right away though.
+async fn apply_fixture(
+    cluster: &Cluster,
+    database: &str,
+    path: &Path,
+    file_name: &str,
+) -> Result<()> {
+    let script = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read fixture {}", path.display()))?;
+    let mut transaction = cluster
+        .backend
+        .begin_transaction(Some(database), None)
+        .await
+        .map_err(|(_, e)| e)?;
+    for statement in split_statements(&script, cluster.backend.dialect()) {
+        if let Err(e) = transaction.execute_raw(&statement.sql).await {
+            transaction.rollback().await?;
+            return Err(anyhow!("{}:{}: {e:#}", path.display(), statement.line));
+        }
+    }
+    transaction
+        .execute_raw(&format!(
+            "INSERT INTO {APPLIED_FIXTURES_TABLE} (file_name) VALUES ('{}')",
+            file_name.replace('\'', "''")
+        ))
+        .await?;
+    transaction.commit().await
+}
+
+/// Create and seed the databases listed in `fixtures`
+pub(crate) async fn seed(clusters: &Clusters, fixtures: &[FixturesConfig]) -> Result<()> {
+    for fixture in fixtures {
+        let cluster = match &fixture.cluster {
+            Some(name) => clusters.named(name),
+            None => clusters.get(None),
+        }
+        .ok_or_else(|| {
+            anyhow!(
+                "No cluster '{}' to seed database '{}' on",
+                fixture.cluster.as_deref().unwrap_or("default"),
+                fixture.database
+            )
+        })?;
+
+        seed_database(cluster, fixture).await.with_context(|| {
+            format!(
+                "Failed to seed database '{}' of cluster '{}'",
+                fixture.database, cluster.name
+            )
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+    use serde_json::Value;
+
+    use crate::backend::{Backend, PostgresBackend, SqliteBackend};
+    use crate::databases::{self, Databases};
+    use crate::test_util::call;
+
+    #[test]
+    fn test_split_statements() {
+        let script = "-- Schema\nCREATE TABLE t (x TEXT);\n\nINSERT INTO t VALUES ('a;b'), (\"c\");\n/* done; */ INSERT INTO t VALUES ($$d;e$$)\n";
+        assert_eq!(
+            split_statements(script, Dialect::Postgres),
+            vec![
+                Statement {
+                    line: 2,
+                    sql: "-- Schema\nCREATE TABLE t (x TEXT)".to_string()
+                },
+                Statement {
+                    line: 4,
+                    sql: "INSERT INTO t VALUES ('a;b'), (\"c\")".to_string()
+                },
+                Statement {
+                    line: 5,
+                    sql: "/* done; */ INSERT INTO t VALUES ($$d;e$$)".to_string()
+                },
+            ]
+        );
+        assert!(split_statements("-- nothing;\n  ", Dialect::Postgres).is_empty());
+
+        // `#` is an operator in PostgreSQL, a comment in MySQL
+        let script = "SELECT data #> '{a}' FROM t; SELECT 1";
+        assert_eq!(split_statements(script, Dialect::Postgres).len(), 2);
+        assert_eq!(split_statements(script, Dialect::MySql).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_seed() {
+        let directory =
+            std::env::temp_dir().join(format!("rds-lite-fixtures-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("001_schema.sql"),
+            "CREATE TABLE t (x INTEGER);\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("002_seed.sql"),
+            "INSERT INTO t VALUES (1);\nINSERT INTO t VALUES (2);\n",
+        )
+        .unwrap();
+        std::fs::write(directory.join("README.md"), "Not a fixture").unwrap();
+
+        let mut clusters = Clusters::default();
+        clusters.add(Cluster {
+            name: "default".to_string(),
+            resource_arn: None,
+            backend: Arc::new(SqliteBackend::in_memory()),
+        });
+        let fixtures = [FixturesConfig {
+            cluster: None,
+            database: "app".to_string(),
+            directory: directory.clone(),
+            snapshot: false,
+        }];
+
+        // Files already applied are skipped
+        seed(&clusters, &fixtures).await.unwrap();
+        seed(&clusters, &fixtures).await.unwrap();
+
+        std::fs::write(
+            directory.join("003_broken.sql"),
+            "INSERT INTO t VALUES (3);\n\n-- Oops\nINSERT INTO missing VALUES (4);\n",
+        )
+        .unwrap();
+        let err = seed(&clusters, &fixtures).await.unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("003_broken.sql:4: "), "{message}");
+        assert!(message.contains("no such table: missing"), "{message}");
+
+        let backend = clusters.get(None).unwrap().backend.as_ref();
+        // Nothing of the failed file is left, so that it can be fixed and applied again
+        assert_eq!(count(backend, "app").await, FieldDef::LongValue(2));
+
+        std::fs::write(
+            directory.join("003_broken.sql"),
+            "INSERT INTO t VALUES (3);\nINSERT INTO t VALUES (4);\n",
+        )
+        .unwrap();
+        seed(&clusters, &fixtures).await.unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(count(backend, "app").await, FieldDef::LongValue(4));
+    }
+
+    /// Needs a PostgreSQL server, e.g. `DATABASE_URL=postgres://postgres@localhost/postgres`
+    #[tokio::test]
+    async fn test_reset_seeded_postgres() {
+        let Some(url) = std::env::var("DATABASE_URL")
+            .ok()
+            .filter(|url| url.starts_with("postgres"))
+        else {
+            return;
+        };
+        let database = format!("rds_lite_seeded_{}", std::process::id());
+        let directory = std::env::temp_dir().join(&database);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("001_schema.sql"),
+            "CREATE TABLE t (x INTEGER);\nINSERT INTO t VALUES (1), (2);\n",
+        )
+        .unwrap();
+
+        let mut clusters = Clusters::default();
+        clusters.add(Cluster {
+            name: "default".to_string(),
+            resource_arn: None,
+            backend: Arc::new(PostgresBackend::connect_lazy(&url).unwrap()),
+        });
+        let clusters = Arc::new(clusters);
+        let fixtures = [FixturesConfig {
+            cluster: None,
+            database: database.clone(),
+            directory: directory.clone(),
+            snapshot: true,
+        }];
+        seed(&clusters, &fixtures).await.unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let backend = clusters.get(None).unwrap().backend.as_ref();
+        backend
+            .execute_raw(&database, "INSERT INTO t VALUES (3)")
+            .await
+            .unwrap();
+        assert_eq!(count(backend, &database).await, FieldDef::LongValue(3));
+
+        let app = databases::admin_router(Arc::new(Databases::new(
+            clusters.clone(),
+            Duration::from_secs(60),
+        )));
+        let uri = format!("/_admin/databases/{database}/reset");
+        let (status, body) = call(&app, "POST", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT, "{body}");
+        assert_eq!(count(backend, &database).await, FieldDef::LongValue(2));
+
+        backend.drop_database(&database).await.unwrap();
+        backend
+            .drop_database(&format!("{database}_rds_lite_snapshot"))
+            .await
+            .unwrap();
+    }
+
+    /// The number of rows of the table `t` of `database`
+    async fn count(backend: &dyn Backend, database: &str) -> FieldDef {
+        let output = backend
+            .run_query(
+                Some(database),
+                None,
+                "SELECT count(*) FROM t",
+                &[],
+                vec![vec![]],
+            )
+            .await
+            .unwrap();
+        let Either::Left(records) = output else {
+            panic!("SELECT returned no records");
+        };
+        records.rows[0][0].clone()
+    }
+}
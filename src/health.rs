@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{Json, Router, extract::State, http::StatusCode, routing::get};
+use log::warn;
+use serde::Serialize;
+use tokio::task::JoinSet;
+
+use crate::cluster::Clusters;
+
+#[derive(Clone)]
+struct HealthState {
+    clusters: Arc<Clusters>,
+    timeout: Duration,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadinessDef {
+    ready: bool,
+    clusters: Vec<ClusterReadinessDef>,
+}
+
+#[derive(Debug, Serialize)]
+struct ClusterReadinessDef {
+    name: String,
+    ready: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The process is up and serving requests
+async fn health() -> &'static str {
+    "OK"
+}
+
+/// Every cluster's database can run a statement
+async fn ready(State(state): State<HealthState>) -> (StatusCode, Json<ReadinessDef>) {
+    let mut pings = JoinSet::new();
+    for (index, cluster) in state.clusters.iter().enumerate() {
+        let cluster = cluster.clone();
+        let timeout = state.timeout;
+        pings.spawn(async move {
+            let error = match tokio::time::timeout(timeout, cluster.backend.ping()).await {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(format!("{e:#}")),
+                Err(_) => Some(format!("Timed out after {}ms", timeout.as_millis())),
+            };
+            let readiness = ClusterReadinessDef {
+                name: cluster.name.clone(),
+                ready: error.is_none(),
+                error,
+            };
+            (index, readiness)
+        });
+    }
+
+    let mut clusters = pings.join_all().await;
+    clusters.sort_by_key(|(index, _)| *index);
+    let clusters: Vec<_> = clusters.into_iter().map(|(_, c)| c).collect();
+
+    for cluster in clusters.iter().filter(|c| !c.ready) {
+        warn!(
+            "Cluster '{}' is not ready: {}",
+            cluster.name,
+            cluster.error.as_deref().unwrap_or_default()
+        );
+    }
+
+    let ready = clusters.iter().all(|c| c.ready);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(ReadinessDef { ready, clusters }))
+}
+
+/// `/health` and `/ready` probes, for docker-compose healthchecks and the like.
+/// Readiness waits at most `timeout` for each cluster to answer.
+pub(crate) fn router(clusters: Arc<Clusters>, timeout: Duration) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+        .with_state(HealthState { clusters, timeout })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use crate::backend::{self, SqliteBackend};
+    use crate::cluster::Cluster;
+    use crate::config::PoolConfig;
+
+    #[tokio::test]
+    async fn test_ready_names_the_unavailable_cluster() {
+        let mut clusters = Clusters::default();
+        clusters.add(Cluster {
+            name: "lite".to_string(),
+            resource_arn: None,
+            backend: Arc::new(SqliteBackend::in_memory()),
+        });
+        clusters.add(Cluster {
+            name: "down".to_string(),
+            resource_arn: Some("arn:down".to_string()),
+            // Nothing listens on port 1
+            backend: backend::connect_lazy("mysql://root@127.0.0.1:1", &PoolConfig::default())
+                .unwrap(),
+        });
+        let app = router(Arc::new(clusters), Duration::from_millis(500));
+
+        let response = app
+            .oneshot(Request::get("/ready").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["ready"], json!(false));
+        assert_eq!(body["clusters"][0], json!({"name": "lite", "ready": true}));
+        assert_eq!(body["clusters"][1]["name"], json!("down"));
+        assert_eq!(body["clusters"][1]["ready"], json!(false));
+    }
+}
@@ -0,0 +1,432 @@
+//! rds-lite simulates a minuscule subset of the AWS RDS Data API, running the received
+//! statements on a local database.
+//!
+//! Besides running the `rds-lite` binary, the server can be started from Rust code, e.g.
+//! from integration tests:
+//!
+//! ```no_run
+//! # async fn example(pool: sqlx::MySqlPool) -> anyhow::Result<()> {
+//! let server = rds_lite::Builder::new().pool(pool).spawn().await?;
+//! // Point the AWS SDK at `server.endpoint`...
+//! server.shutdown.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::future::IntoFuture;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use axum::extract::DefaultBodyLimit;
+use axum::serve::Listener;
+use axum::{Router, middleware};
+use futures_util::future::{self, BoxFuture, FutureExt};
+use log::{error, info, warn};
+use sqlx::MySqlPool;
+use tokio::net::TcpListener;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+
+mod api;
+pub mod aws;
+pub mod backend;
+pub mod cluster;
+pub mod config;
+mod databases;
+pub mod error;
+mod faults;
+mod fixtures;
+mod health;
+pub mod logging;
+mod metrics;
+mod pause;
+pub mod query;
+mod recording;
+mod replay;
+mod startup;
+mod statements;
+#[cfg(test)]
+mod test_util;
+mod throttling;
+mod tls;
+mod transactions;
+
+use backend::{Backend, MySqlBackend};
+use cluster::{Cluster, Clusters};
+pub use config::Config;
+use databases::Databases;
+use faults::Faults;
+use pause::Pauses;
+use recording::Recorder;
+use replay::Replayer;
+use statements::StatementLog;
+use throttling::Throttler;
+use tls::TlsListener;
+use transactions::Transactions;
+
+/// Assembles an rds-lite server from a [`Config`] and the databases to run statements on
+#[derive(Default)]
+pub struct Builder {
+    config: Config,
+    clusters: Vec<Cluster>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Run statements on an existing MySQL pool instead of connecting to `database_url`
+    pub fn pool(self, pool: MySqlPool) -> Self {
+        self.backend(MySqlBackend::new(pool))
+    }
+
+    /// Run statements on `backend` instead of connecting to `database_url`
+    pub fn backend(self, backend: impl Backend + 'static) -> Self {
+        self.cluster("default", None, backend)
+    }
+
+    /// Add a cluster serving the requests for `resource_arn`, or all the requests not
+    /// matching another cluster if `None`. It replaces the configured cluster with the
+    /// same name, if any.
+    pub fn cluster(
+        mut self,
+        name: &str,
+        resource_arn: Option<&str>,
+        backend: impl Backend + 'static,
+    ) -> Self {
+        self.clusters.push(Cluster {
+            name: name.to_string(),
+            resource_arn: resource_arn.map(str::to_string),
+            backend: Arc::new(backend),
+        });
+        self
+    }
+
+    /// Build the axum `Router` serving the Data API. Unlike [`Builder::serve`] and
+    /// [`Builder::spawn`], it doesn't apply the fixtures.
+    pub fn router(mut self) -> Result<Router> {
+        let clusters = self.clusters()?;
+        Ok(self.app_for(clusters)?.router)
+    }
+
+    /// Get the databases ready, then build the `App`
+    async fn start(mut self) -> Result<App> {
+        let clusters = self.clusters()?;
+        if let Some(wait) = &self.config.wait_for_databases {
+            info!("Waiting at most {}ms for the databases", wait.timeout_ms);
+            startup::wait_for_databases(&clusters, wait).await?;
+        }
+        fixtures::seed(&clusters, &self.config.fixtures).await?;
+        self.app_for(clusters)
+    }
+
+    fn clusters(&mut self) -> Result<Arc<Clusters>> {
+        // Use lazy pools so the server can start immediately
+        let mut clusters = Clusters::from_config(&self.config)?;
+        for cluster in std::mem::take(&mut self.clusters) {
+            clusters.add(cluster);
+        }
+        if clusters.is_empty() && self.config.replay_path.is_none() {
+            bail!(
+                "No database configured: set DATABASE_URL, add a [[clusters]] section or set replay_path"
+            );
+        }
+        Ok(Arc::new(clusters))
+    }
+
+    fn app_for(self, clusters: Arc<Clusters>) -> Result<App> {
+        let replayer = match &self.config.replay_path {
+            Some(path) => Some(Replayer::load(path, self.config.replay_match)?),
+            None => None,
+        };
+        let ready_timeout = Duration::from_millis(self.config.ready_timeout_ms);
+
+        let statements = Arc::new(StatementLog::new(self.config.statement_log_size));
+        let transactions = Arc::new(Transactions::new(
+            self.config.transactions,
+            statements.clone(),
+        ));
+        let mut data_api = match replayer {
+            Some(replayer) => replay::router(Arc::new(replayer)),
+            None => api::router(clusters.clone(), transactions.clone(), statements.clone()),
+        };
+        if let Some(path) = &self.config.record_path {
+            info!("Recording requests to {}", path.display());
+            let recorder = Arc::new(Recorder::open(path)?);
+            data_api =
+                data_api.route_layer(middleware::from_fn_with_state(recorder, recording::record));
+        }
+        // Injected faults aren't recorded, but they are counted in the metrics
+        let faults = Arc::new(Faults::new(&self.config.faults)?);
+        let pauses = Arc::new(Pauses::new(clusters.clone(), &self.config));
+        let databases = Arc::new(Databases::new(
+            clusters.clone(),
+            Duration::from_millis(self.config.ephemeral_ttl_ms),
+        ));
+        let data_api = data_api
+            .route_layer(middleware::from_fn_with_state(
+                faults.clone(),
+                faults::inject,
+            ))
+            .route_layer(middleware::from_fn_with_state(pauses.clone(), pause::check))
+            .route_layer(middleware::from_fn_with_state(
+                Arc::new(Throttler::new(self.config.throttling.clone())),
+                throttling::throttle,
+            ))
+            .route_layer(middleware::from_fn(metrics::track_requests))
+            .route_layer(middleware::from_fn(logging::assign_request_id))
+            // Applies to the middlewares reading the body as well as to the handlers
+            .route_layer(DefaultBodyLimit::max(api::MAX_REQUEST_SIZE));
+
+        let router = data_api
+            .merge(faults::admin_router(faults))
+            .merge(pause::admin_router(pauses))
+            .merge(databases::admin_router(databases.clone()))
+            .merge(transactions::admin_router(transactions.clone()))
+            .merge(statements::admin_router(statements))
+            .merge(health::router(clusters.clone(), ready_timeout))
+            .merge(metrics::router(clusters.clone()));
+        Ok(App {
+            router,
+            clusters,
+            transactions,
+            databases,
+        })
+    }
+
+    /// Listen on the configured address and port until the process gets SIGTERM or SIGINT.
+    /// It then stops accepting requests, gives the in-flight ones `shutdown_timeout_ms` to
+    /// complete, rolls back the open transactions, drops the ephemeral databases and closes
+    /// the pools, waiting at most as long again for all of it.
+    pub async fn serve(self) -> Result<()> {
+        let bind_address = self.config.bind_address;
+        let port = self.config.port;
+        let shutdown_timeout = Duration::from_millis(self.config.shutdown_timeout_ms);
+        // Without a port of its own, HTTPS replaces HTTP on the main port
+        let tls = match &self.config.tls {
+            Some(config) => Some((config.port.unwrap_or(port), tls::acceptor(config)?)),
+            None => None,
+        };
+        let app = self.start().await?;
+
+        let (stop, mut stopping) = watch::channel(false);
+        let mut servers = vec![];
+        if tls
+            .as_ref()
+            .is_none_or(|(https_port, _)| *https_port != port)
+        {
+            let http_addr = SocketAddr::new(bind_address, port);
+            let listener = bind(http_addr).await?;
+            info!("Listening on {http_addr}");
+            servers.push(serve_on(listener, app.router.clone(), stopping.clone()));
+        }
+        if let Some((https_port, acceptor)) = tls {
+            let https_addr = SocketAddr::new(bind_address, https_port);
+            let listener = TlsListener::new(bind(https_addr).await?, acceptor)?;
+            info!("Listening on {https_addr} with TLS");
+            servers.push(serve_on(listener, app.router.clone(), stopping.clone()));
+        }
+
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            info!("Shutting down, waiting for the in-flight requests");
+            let _ = stop.send(true);
+        });
+        let result = tokio::select! {
+            result = future::try_join_all(servers) => {
+                result.map(drop).context("Failed to start server")
+            }
+            _ = async {
+                let _ = stopping.wait_for(|stopping| *stopping).await;
+                tokio::time::sleep(shutdown_timeout).await;
+            } => {
+                warn!(
+                    "Requests still running after {}ms, cancelling them",
+                    shutdown_timeout.as_millis()
+                );
+                Ok(())
+            }
+        };
+
+        // Cancelled requests may still hold connections until the process exits
+        let cleanup = async {
+            app.transactions.rollback_all().await;
+            app.databases.release_all().await;
+            for cluster in app.clusters.iter() {
+                cluster.backend.close().await;
+            }
+        };
+        if tokio::time::timeout(shutdown_timeout, cleanup)
+            .await
+            .is_err()
+        {
+            warn!("Connections still in use, exiting without closing them");
+        }
+        info!("Stopped");
+        result
+    }
+
+    /// Start the server in the background on an ephemeral loopback port, ignoring the
+    /// configured port and TLS. Meant for tests, which can point their client at `endpoint`.
+    pub async fn spawn(self) -> Result<SpawnedServer> {
+        let app = self.start().await?;
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let endpoint = format!("http://{}", listener.local_addr()?);
+
+        let (sender, receiver) = oneshot::channel::<()>();
+        let task = tokio::spawn(async move {
+            let result = axum::serve(listener, app.router)
+                .with_graceful_shutdown(async {
+                    // Also shut down if the handle is dropped
+                    let _ = receiver.await;
+                })
+                .await
+                .context("Failed to start server");
+            // The pools are left open, as they may belong to the caller
+            app.transactions.rollback_all().await;
+            app.databases.release_all().await;
+            result
+        });
+
+        Ok(SpawnedServer {
+            endpoint,
+            shutdown: ShutdownHandle { sender, task },
+        })
+    }
+}
+
+/// The routes of a server, with what it cleans up when it stops
+struct App {
+    router: Router,
+    clusters: Arc<Clusters>,
+    transactions: Arc<Transactions>,
+    databases: Arc<Databases>,
+}
+
+async fn bind(addr: SocketAddr) -> Result<TcpListener> {
+    TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {addr}"))
+}
+
+/// Serve `router` on `listener` until `stopping` turns true, then wait for the in-flight
+/// requests to complete
+fn serve_on<L>(
+    listener: L,
+    router: Router,
+    mut stopping: watch::Receiver<bool>,
+) -> BoxFuture<'static, std::io::Result<()>>
+where
+    L: Listener,
+    L::Addr: std::fmt::Debug,
+{
+    axum::serve(listener, router)
+        .with_graceful_shutdown(async move {
+            let _ = stopping.wait_for(|stopping| *stopping).await;
+        })
+        .into_future()
+        .boxed()
+}
+
+/// Wait for SIGINT (Ctrl-C) or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// A server started with [`Builder::spawn`]
+pub struct SpawnedServer {
+    /// Base URL of the server, e.g. `http://127.0.0.1:41234`
+    pub endpoint: String,
+    pub shutdown: ShutdownHandle,
+}
+
+/// Stops a server started with [`Builder::spawn`] when used or dropped
+pub struct ShutdownHandle {
+    sender: oneshot::Sender<()>,
+    task: JoinHandle<Result<()>>,
+}
+
+impl ShutdownHandle {
+    /// Stop the server, wait for in-flight requests to complete, then roll back the open
+    /// transactions
+    pub async fn shutdown(self) {
+        let _ = self.sender.send(());
+        let _ = self.task.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::backend::SqliteBackend;
+
+    #[tokio::test]
+    async fn test_spawn() {
+        let server = Builder::new()
+            .backend(SqliteBackend::in_memory())
+            .spawn()
+            .await
+            .unwrap();
+
+        let body = r#"{"sql": "SELECT 1"}"#;
+        let addr = server.endpoint.trim_start_matches("http://");
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                format!(
+                    "POST /Execute HTTP/1.1\r\nHost: {addr}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains(r#""records":[[{"longValue":1}]]"#));
+
+        server.shutdown.shutdown().await;
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
+    #[test]
+    fn test_router_requires_a_database() {
+        assert!(Builder::new().router().is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Request ids, logged with every Data API request, and the JSON log format
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Instant;
+
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+use chrono::{SecondsFormat, Utc};
+use log::kv::{self, VisitSource, VisitValue};
+use log::{Level, Record, error, log};
+use serde_json::{Map, Value, json};
+
+use crate::api::{read_fields, request_action, request_fields};
+use crate::error::ERROR_TYPE_HEADER;
+
+/// Header AWS uses to tell clients the id of their request, `x-amzn-RequestId`
+pub const REQUEST_ID_HEADER: &str = "x-amzn-requestid";
+
+tokio::task_local! {
+    /// Id of the request being served, for the log lines written while serving it
+    static REQUEST_ID: String;
+}
+
+/// A new request id, a random UUID like the ones of AWS
+fn new_request_id() -> String {
+    uuid::Builder::from_random_bytes(rand::random())
+        .into_uuid()
+        .to_string()
+}
+
+/// Id of the Data API request being served by the current task, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Middleware giving every request an id, answered in `x-amzn-RequestId`, and logging the
+/// outcome of the request. As the outermost one, it reads the `RequestFields` of the request
+/// for the middlewares after it.
+pub(crate) async fn assign_request_id(request: Request, next: Next) -> Response {
+    let id = new_request_id();
+    let action = request_action(&request);
+
+    let start = Instant::now();
+    let (mut response, database) = match read_fields(request).await {
+        Ok(request) => {
+            let database = request_fields(&request).database.clone();
+            let response = REQUEST_ID.scope(id.clone(), next.run(request)).await;
+            (response, database)
+        }
+        Err(response) => (response, None),
+    };
+    let duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    let status = response.status();
+    let error_type = response
+        .headers()
+        .get(ERROR_TYPE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let level = if status.is_server_error() {
+        Level::Warn
+    } else {
+        Level::Info
+    };
+    log!(
+        level,
+        request_id = id.as_str(),
+        action,
+        database = database.as_deref(),
+        status = status.as_u16(),
+        duration_ms,
+        error_type = error_type.as_deref();
+        "{action} answered {status} in {duration_ms:.1} ms"
+    );
+
+    match HeaderValue::from_str(&id) {
+        Ok(value) => {
+            response.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        Err(e) => error!("Invalid request id {id}: {e}"),
+    }
+    response
+}
+
+/// Write `record` as a JSON object with its timestamp, level, target, message, the id of the
+/// request being served if any, and its key-values. Meant for [`env_logger::Builder::format`].
+pub fn format_json(buf: &mut impl Write, record: &Record) -> std::io::Result<()> {
+    let mut line = Map::new();
+    line.insert(
+        "timestamp".to_string(),
+        json!(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+    );
+    line.insert("level".to_string(), json!(record.level().as_str()));
+    line.insert("target".to_string(), json!(record.target()));
+    line.insert("message".to_string(), json!(record.args().to_string()));
+    if let Some(id) = current_request_id() {
+        line.insert("request_id".to_string(), json!(id));
+    }
+
+    let mut fields = Fields::default();
+    // Visiting never fails, it only collects
+    let _ = record.key_values().visit(&mut fields);
+    line.extend(fields.0.into_iter().filter(|(_, value)| !value.is_null()));
+
+    writeln!(buf, "{}", Value::Object(line))
+}
+
+/// Key-values of a log record as JSON values
+#[derive(Default)]
+struct Fields(BTreeMap<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let mut json = JsonValue(Value::Null);
+        value.visit(&mut json)?;
+        self.0.insert(key.to_string(), json.0);
+        Ok(())
+    }
+}
+
+struct JsonValue(Value);
+
+impl<'v> VisitValue<'v> for JsonValue {
+    fn visit_any(&mut self, value: kv::Value) -> Result<(), kv::Error> {
+        self.0 = json!(value.to_string());
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        self.0 = Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        self.0 = json!(value);
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        self.0 = json!(value);
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), kv::Error> {
+        self.0 = json!(value);
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        self.0 = json!(value);
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), kv::Error> {
+        self.0 = json!(value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    use crate::Builder;
+    use crate::api::MAX_REQUEST_SIZE;
+    use crate::backend::SqliteBackend;
+
+    async fn request_id(app: &Router, action: &str, body: Value) -> (StatusCode, String) {
+        let request = Request::post(format!("/{action}"))
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        (response.status(), id.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_request_ids() {
+        let app = Builder::new()
+            .backend(SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+
+        let (status, first) = request_id(&app, "Execute", json!({"sql": "SELECT 1"})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(uuid::Uuid::parse_str(&first).unwrap().to_string(), first);
+
+        let body = json!({"transactionId": "unknown"});
+        let (status, second) = request_id(&app, "CommitTransaction", body).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_ne!(first, second);
+
+        // Bodies over the limit of the Data API are answered with a 413, still with an id
+        let sql = format!("SELECT '{}'", "x".repeat(MAX_REQUEST_SIZE));
+        let (status, third) = request_id(&app, "Execute", json!({"sql": sql})).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_ne!(second, third);
+    }
+
+    #[tokio::test]
+    async fn test_format_json() {
+        let format = || {
+            let mut buf = Vec::new();
+            let kvs = [("action", "ExecuteStatement"), ("database", "app")];
+            let record = Record::builder()
+                .level(Level::Warn)
+                .target("rds_lite::api")
+                .args(format_args!("Something failed"))
+                .key_values(&kvs)
+                .build();
+            format_json(&mut buf, &record).unwrap();
+            serde_json::from_slice::<Value>(&buf).unwrap()
+        };
+
+        let line = format();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["target"], "rds_lite::api");
+        assert_eq!(line["message"], "Something failed");
+        assert_eq!(line["action"], "ExecuteStatement");
+        assert_eq!(line["database"], "app");
+        assert!(line["timestamp"].is_string());
+        assert!(line.get("request_id").is_none());
+
+        let line = REQUEST_ID.scope("42".to_string(), async { format() }).await;
+        assert_eq!(line["request_id"], "42");
+    }
+}
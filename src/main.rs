@@ -1,107 +1,141 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
 use anyhow::Result;
-use axum::{
-    Router,
-    extract::{Json, State},
-    http::StatusCode,
-    routing::post,
-};
-use log::{error, info};
-use sqlx::mysql::MySqlPoolOptions;
-use sqlx::{Either, MySqlPool};
-use std::net::SocketAddr;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rds_lite::config::WaitConfig;
+use rds_lite::{Builder, Config};
 
-use crate::aws::{
-    BatchExecuteStatementInputDef, BatchExecuteStatementOutputDef, ExecuteStatementInputDef,
-    ExecuteStatementOutputDef,
-};
+/// Simulates a minuscule subset of the AWS RDS Data API on a local database
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Defaults to `serve`
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-mod aws;
-mod query;
-use query::run_query;
+#[derive(Subcommand)]
+enum Command {
+    /// Run the Data API server
+    Serve(ServeArgs),
+    /// Validate the configuration and exit
+    CheckConfig(ConfigArgs),
+    /// Print the version and exit
+    Version,
+}
 
-macro_rules! get_or_400 {
-    ($input:expr, $field:ident) => {
-        match &$input.$field {
-            Some(value) => value,
-            None => {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    format!("Missing required field: {}", stringify!($field)),
-                ))
-            }
-        }
-    };
+#[derive(Args, Default)]
+struct ConfigArgs {
+    /// Configuration file, defaults to the RDS_LITE_CONFIG environment variable
+    #[arg(short, long)]
+    config: Option<PathBuf>,
 }
 
-async fn execute_statement(
-    State(pool): State<MySqlPool>,
-    Json(input): Json<ExecuteStatementInputDef>,
-) -> Result<Json<ExecuteStatementOutputDef>, (StatusCode, String)> {
-    let sql = get_or_400!(input, sql);
-    let params = input.parameters.unwrap_or(vec![]);
+#[derive(Args, Default)]
+struct ServeArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Port to listen on, overriding the configuration
+    #[arg(short, long)]
+    port: Option<u16>,
+    /// Address to listen on, e.g. 127.0.0.1 or ::1, overriding the configuration
+    #[arg(short, long)]
+    bind: Option<IpAddr>,
+    /// Log filter in env_logger syntax, e.g. `info` or `rds_lite=debug`, overriding RUST_LOG
+    #[arg(long)]
+    log_level: Option<String>,
+    /// Format of the log lines
+    #[arg(long, value_enum, default_value_t)]
+    log_format: LogFormat,
+    /// Append every Data API request and its response to this JSON Lines file
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+    /// Answer requests from a recording made with `--record` instead of a database
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+    /// Wait at most this long for the databases to accept connections before serving
+    #[arg(long, value_name = "MS")]
+    wait_for_databases: Option<u64>,
+}
 
-    let output = match run_query(&pool, input.database, input.schema, sql, vec![params]).await {
-        Ok(Either::Left(records)) => ExecuteStatementOutputDef {
-            records: Some(records),
-            ..ExecuteStatementOutputDef::default()
-        },
-        Ok(Either::Right(affected_rows)) => ExecuteStatementOutputDef {
-            number_of_records_updated: affected_rows as i64,
-            ..ExecuteStatementOutputDef::default()
-        },
-        Err((status, err)) => {
-            error!("Error executing statement: {err}");
-            return Err((status, err.to_string()));
-        }
-    };
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// A JSON object per line, with the request id of the Data API calls
+    Json,
+}
 
-    Ok(Json(output))
+fn init_logging(log_level: Option<&str>, log_format: LogFormat) {
+    let mut builder = env_logger::Builder::from_env("RUST_LOG");
+    if let Some(filters) = log_level {
+        builder.parse_filters(filters);
+    }
+    if let LogFormat::Json = log_format {
+        builder.format(rds_lite::logging::format_json);
+    }
+    builder.init();
 }
 
-async fn batch_execute_statement(
-    State(pool): State<MySqlPool>,
-    Json(input): Json<BatchExecuteStatementInputDef>,
-) -> Result<Json<BatchExecuteStatementOutputDef>, (StatusCode, String)> {
-    let sql = get_or_400!(input, sql);
-    let params = input.parameter_sets.unwrap_or(vec![]);
+fn check_config(args: ConfigArgs) -> Result<()> {
+    // Shows the warnings about the configuration, e.g. `RDS_LITE_*` variables naming no setting
+    env_logger::Builder::from_env(env_logger::Env::new().filter_or("RUST_LOG", "warn")).init();
+    let config = Config::load(args.config.as_deref())?;
+    println!("Configuration is valid");
+    for cluster in config.all_clusters() {
+        // Only the scheme is printed, the rest of the URL may contain a password
+        let scheme = cluster.database_url.split(':').next().unwrap_or_default();
+        let resource_arn = cluster.resource_arn.as_deref().unwrap_or("(default)");
+        println!("  cluster '{}': {resource_arn} -> {scheme}", cluster.name);
+    }
+    if let Some(path) = &config.replay_path {
+        println!("  replaying {}", path.display());
+    }
+    if let Some(tls) = &config.tls {
+        let port = tls.port.unwrap_or(config.port);
+        println!("  TLS on port {port}: {}", tls.certificate_path.display());
+    }
+    Ok(())
+}
 
-    let output = match run_query(&pool, input.database, input.schema, sql, params).await {
-        Ok(Either::Left(_records)) => BatchExecuteStatementOutputDef {
-            ..BatchExecuteStatementOutputDef::default()
-        },
-        Ok(Either::Right(_affected_rows)) => BatchExecuteStatementOutputDef {
-            ..BatchExecuteStatementOutputDef::default()
-        },
-        Err((status, err)) => return Err((status, err.to_string())),
-    };
+async fn serve(args: ServeArgs) -> Result<()> {
+    init_logging(args.log_level.as_deref(), args.log_format);
 
-    Ok(Json(output))
+    let mut config = Config::read(args.config.config.as_deref())?;
+    if let Some(port) = args.port {
+        config.port = port;
+    }
+    if let Some(bind) = args.bind {
+        config.bind_address = bind;
+    }
+    if let Some(record) = args.record {
+        config.record_path = Some(record);
+    }
+    if let Some(replay) = args.replay {
+        config.replay_path = Some(replay);
+    }
+    if let Some(timeout_ms) = args.wait_for_databases {
+        config.wait_for_databases = Some(WaitConfig {
+            timeout_ms,
+            ..config.wait_for_databases.unwrap_or_default()
+        });
+    }
+    config.validate()?;
+    Builder::new().config(config).serve().await
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::Builder::from_env("RUST_LOG").init();
-
-    let port = std::env::var("PORT")
-        .unwrap_or_else(|_| "3000".to_string())
-        .parse()
-        .expect("PORT must be a valid u16 number");
-    let url = std::env::var("DATABASE_URL").expect("Missing required env var DATABASE_URL");
-    // Use a lazy pool so the server can start immediately
-    let pool = MySqlPoolOptions::new()
-        .connect_lazy(&url)
-        .expect("Invalid DATABASE_URL");
+    let cli = Cli::parse();
 
-    let app = Router::new()
-        .route("/Execute", post(execute_statement))
-        .route("/BatchExecute", post(batch_execute_statement))
-        .with_state(pool);
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    info!("Listening on {addr}");
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app)
-        .await
-        .expect("Failed to start server");
-    Ok(())
+    match cli.command.unwrap_or(Command::Serve(ServeArgs::default())) {
+        Command::Serve(args) => serve(args).await,
+        Command::CheckConfig(args) => check_config(args),
+        Command::Version => {
+            println!("rds-lite {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }
+    }
 }
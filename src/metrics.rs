@@ -0,0 +1,242 @@
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use prometheus::{
+    Encoder, HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder, register_histogram_vec,
+    register_int_counter_vec, register_int_gauge_vec,
+};
+use sqlx::Either;
+
+use crate::api::request_action;
+use crate::cluster::Clusters;
+use crate::query::{QueryError, QueryOutput};
+
+static REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "rds_lite_requests_total",
+        "Data API requests, by action and HTTP status",
+        &["action", "status"]
+    )
+    .unwrap()
+});
+
+static REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "rds_lite_request_duration_seconds",
+        "Time taken to answer Data API requests, by action and HTTP status",
+        &["action", "status"]
+    )
+    .unwrap()
+});
+
+static QUERY_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "rds_lite_query_duration_seconds",
+        "Time taken to run statements on the database, by cluster and outcome",
+        &["cluster", "outcome"]
+    )
+    .unwrap()
+});
+
+static ROWS_RETURNED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "rds_lite_rows_returned_total",
+        "Records returned by statements, by cluster",
+        &["cluster"]
+    )
+    .unwrap()
+});
+
+static ROWS_AFFECTED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "rds_lite_rows_affected_total",
+        "Rows affected by statements, by cluster",
+        &["cluster"]
+    )
+    .unwrap()
+});
+
+static POOL_CONNECTIONS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "rds_lite_pool_connections",
+        "Connections of the database pools, by cluster and state (idle, active or waiting)",
+        &["cluster", "state"]
+    )
+    .unwrap()
+});
+
+static OPEN_TRANSACTIONS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "rds_lite_open_transactions",
+        "Transactions started and not ended yet, by cluster",
+        &["cluster"]
+    )
+    .unwrap()
+});
+
+static EXPIRED_TRANSACTIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "rds_lite_expired_transactions_total",
+        "Transactions rolled back because they expired, by cluster and reason (idle or max_lifetime)",
+        &["cluster", "reason"]
+    )
+    .unwrap()
+});
+
+static REWRITE_CACHE: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "rds_lite_rewrite_cache_total",
+        "Lookups of the rewritten SQL of statements in the cache, by result (hit or miss)",
+        &["result"]
+    )
+    .unwrap()
+});
+
+/// Middleware counting and timing the requests to the Data API routes
+pub(crate) async fn track_requests(request: Request, next: Next) -> Response {
+    let action = request_action(&request);
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let status = response.status().as_u16().to_string();
+
+    REQUESTS.with_label_values(&[action, &status]).inc();
+    REQUEST_DURATION
+        .with_label_values(&[action, &status])
+        .observe(start.elapsed().as_secs_f64());
+    response
+}
+
+/// Record the outcome of a statement run on `cluster` that took `duration`
+pub(crate) fn observe_query(
+    cluster: &str,
+    duration: Duration,
+    result: &Result<QueryOutput, QueryError>,
+) {
+    let outcome = if result.is_ok() { "success" } else { "error" };
+    QUERY_DURATION
+        .with_label_values(&[cluster, outcome])
+        .observe(duration.as_secs_f64());
+
+    match result {
+        Ok(Either::Left(records)) => ROWS_RETURNED
+            .with_label_values(&[cluster])
+            .inc_by(records.rows.len() as u64),
+        Ok(Either::Right(affected_rows)) => ROWS_AFFECTED
+            .with_label_values(&[cluster])
+            .inc_by(*affected_rows),
+        Err(_) => {}
+    }
+}
+
+/// Count a transaction started on `cluster`
+pub(crate) fn transaction_started(cluster: &str) {
+    OPEN_TRANSACTIONS.with_label_values(&[cluster]).inc();
+}
+
+/// Count a transaction of `cluster` ending, because it expired if `expired` is set to the reason
+pub(crate) fn transaction_ended(cluster: &str, expired: Option<&str>) {
+    OPEN_TRANSACTIONS.with_label_values(&[cluster]).dec();
+    if let Some(reason) = expired {
+        EXPIRED_TRANSACTIONS
+            .with_label_values(&[cluster, reason])
+            .inc();
+    }
+}
+
+/// Count a lookup of the rewritten SQL of a statement
+pub(crate) fn rewrite_cache_lookup(hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    REWRITE_CACHE.with_label_values(&[result]).inc();
+}
+
+async fn metrics(State(clusters): State<Arc<Clusters>>) -> Response {
+    // Pool usage is sampled when scraped rather than tracked as it changes
+    for cluster in clusters.iter() {
+        let stats = cluster.backend.pool_stats();
+        for (state, value) in [
+            ("idle", stats.idle),
+            ("active", stats.active),
+            ("waiting", stats.waiting),
+        ] {
+            POOL_CONNECTIONS
+                .with_label_values(&[cluster.name.as_str(), state])
+                .set(value as i64);
+        }
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    ([(header::CONTENT_TYPE, encoder.format_type())], buffer).into_response()
+}
+
+/// `/metrics` endpoint in the Prometheus text format
+pub(crate) fn router(clusters: Arc<Clusters>) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(clusters)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use crate::Builder;
+    use crate::backend::SqliteBackend;
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let app = Builder::new()
+            .cluster("metrics-test", None, SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+
+        let request = Request::post("/Execute")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"sql": "SELECT 1 UNION SELECT 2"}"#))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert!(response.status().is_success());
+
+        for _ in 0..2 {
+            let request = Request::post("/Execute")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{"sql": "SELECT :n AS metrics_test", "parameters": [{"name": "n", "value": {"longValue": 1}}]}"#,
+                ))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert!(response.status().is_success());
+        }
+
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let metrics = String::from_utf8(bytes.to_vec()).unwrap();
+
+        assert!(
+            metrics.contains(r#"rds_lite_requests_total{action="ExecuteStatement",status="200"}"#)
+        );
+        assert!(metrics.contains(r#"rds_lite_rows_returned_total{cluster="metrics-test"} 4"#));
+        assert!(
+            metrics
+                .contains(r#"rds_lite_pool_connections{cluster="metrics-test",state="waiting"} 0"#)
+        );
+        assert!(metrics.contains(r#"rds_lite_rewrite_cache_total{result="hit"}"#));
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    Json, Router,
+    extract::{Path, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use log::info;
+use serde::Serialize;
+
+use crate::api::request_fields;
+use crate::cluster::Clusters;
+use crate::config::{ClusterConfig, Config, default_resume_time_ms};
+use crate::error::AwsError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PauseState {
+    Running { last_request: Instant },
+    Paused,
+    Resuming { until: Instant },
+}
+
+/// Emulates the auto-pause of an Aurora Serverless cluster
+struct ClusterPause {
+    auto_pause_after: Option<Duration>,
+    resume_time: Duration,
+    state: Mutex<PauseState>,
+}
+
+impl ClusterPause {
+    fn new(config: Option<&ClusterConfig>, now: Instant) -> Self {
+        Self {
+            auto_pause_after: config
+                .and_then(|c| c.auto_pause_after_ms)
+                .map(Duration::from_millis),
+            resume_time: Duration::from_millis(
+                config.map_or(default_resume_time_ms(), |c| c.resume_time_ms),
+            ),
+            state: Mutex::new(PauseState::Running { last_request: now }),
+        }
+    }
+
+    /// The state at `now`, once the idle time and warm-up are taken into account
+    fn current(&self, now: Instant) -> PauseState {
+        let mut state = self.state.lock().unwrap();
+        self.advance(&mut state, now);
+        *state
+    }
+
+    fn advance(&self, state: &mut PauseState, now: Instant) {
+        match *state {
+            PauseState::Running { last_request }
+                if self
+                    .auto_pause_after
+                    .is_some_and(|after| now.duration_since(last_request) >= after) =>
+            {
+                *state = PauseState::Paused;
+            }
+            PauseState::Resuming { until } if now >= until => {
+                *state = PauseState::Running { last_request: now };
+            }
+            _ => {}
+        }
+    }
+
+    /// Let a request through, unless the cluster is paused or resuming. A request to a
+    /// paused cluster starts resuming it.
+    fn request(&self, now: Instant) -> Result<(), PauseState> {
+        let mut state = self.state.lock().unwrap();
+        self.advance(&mut state, now);
+        match *state {
+            PauseState::Running { .. } => {
+                *state = PauseState::Running { last_request: now };
+                Ok(())
+            }
+            PauseState::Paused => {
+                *state = PauseState::Resuming {
+                    until: now + self.resume_time,
+                };
+                Err(*state)
+            }
+            PauseState::Resuming { .. } => Err(*state),
+        }
+    }
+}
+
+/// The pause state of every cluster
+pub struct Pauses {
+    clusters: Arc<Clusters>,
+    pauses: HashMap<String, ClusterPause>,
+}
+
+impl Pauses {
+    pub fn new(clusters: Arc<Clusters>, config: &Config) -> Self {
+        let configs = config.all_clusters();
+        let now = Instant::now();
+        let pauses = clusters
+            .iter()
+            .map(|cluster| {
+                let config = configs.iter().find(|c| c.name == cluster.name);
+                (cluster.name.clone(), ClusterPause::new(config, now))
+            })
+            .collect();
+        Self { clusters, pauses }
+    }
+
+    /// Whether requests have to be looked at: a cluster may auto-pause, so its requests
+    /// postpone the pause, or a cluster isn't running
+    fn is_active(&self, now: Instant) -> bool {
+        self.pauses.values().any(|pause| {
+            pause.auto_pause_after.is_some()
+                || !matches!(pause.current(now), PauseState::Running { .. })
+        })
+    }
+}
+
+/// Middleware answering `DatabaseResumingException` while the requested cluster resumes
+pub(crate) async fn check(
+    State(pauses): State<Arc<Pauses>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Only the requests that may have to wait have their cluster looked up
+    if !pauses.is_active(Instant::now()) {
+        return next.run(request).await;
+    }
+
+    // Requests for unknown clusters get their error from the handler
+    let pause = pauses
+        .clusters
+        .get(request_fields(&request).resource_arn.as_deref())
+        .and_then(|cluster| Some((cluster, pauses.pauses.get(&cluster.name)?)));
+    if let Some((cluster, pause)) = pause
+        && let Err(state) = pause.request(Instant::now())
+    {
+        if let PauseState::Resuming { until } = state {
+            info!(
+                "Cluster '{}' is resuming for another {}ms",
+                cluster.name,
+                until.saturating_duration_since(Instant::now()).as_millis()
+            );
+        }
+        return AwsError::new(
+            "DatabaseResumingException",
+            "The database is resuming after being auto-paused. Please wait a few seconds and try again.",
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+#[derive(Debug, Serialize)]
+struct ClusterStateDef {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resource_arn: Option<String>,
+    state: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resumed_in_ms: Option<u128>,
+}
+
+async fn list_clusters(State(pauses): State<Arc<Pauses>>) -> Json<Vec<ClusterStateDef>> {
+    let now = Instant::now();
+    let clusters = pauses
+        .clusters
+        .iter()
+        .filter_map(|cluster| {
+            let (state, resumed_in_ms) = match pauses.pauses.get(&cluster.name)?.current(now) {
+                PauseState::Running { .. } => ("running", None),
+                PauseState::Paused => ("paused", None),
+                PauseState::Resuming { until } => (
+                    "resuming",
+                    Some(until.saturating_duration_since(now).as_millis()),
+                ),
+            };
+            Some(ClusterStateDef {
+                name: cluster.name.clone(),
+                resource_arn: cluster.resource_arn.clone(),
+                state,
+                resumed_in_ms,
+            })
+        })
+        .collect();
+    Json(clusters)
+}
+
+async fn pause_cluster(State(pauses): State<Arc<Pauses>>, Path(name): Path<String>) -> StatusCode {
+    set_state(&pauses, &name, PauseState::Paused)
+}
+
+async fn resume_cluster(State(pauses): State<Arc<Pauses>>, Path(name): Path<String>) -> StatusCode {
+    let state = PauseState::Running {
+        last_request: Instant::now(),
+    };
+    set_state(&pauses, &name, state)
+}
+
+fn set_state(pauses: &Pauses, name: &str, state: PauseState) -> StatusCode {
+    match pauses.pauses.get(name) {
+        Some(pause) => {
+            info!("Cluster '{name}' is now {state:?}");
+            *pause.state.lock().unwrap() = state;
+            StatusCode::NO_CONTENT
+        }
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+/// `/_admin/clusters` endpoints showing, pausing and immediately resuming clusters
+pub(crate) fn admin_router(pauses: Arc<Pauses>) -> Router {
+    Router::new()
+        .route("/_admin/clusters", get(list_clusters))
+        .route("/_admin/clusters/{name}/pause", post(pause_cluster))
+        .route("/_admin/clusters/{name}/resume", post(resume_cluster))
+        .with_state(pauses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::SqliteBackend;
+    use crate::cluster::Cluster;
+
+    #[test]
+    fn test_auto_pause_and_resume() {
+        let start = Instant::now();
+        let pause = ClusterPause {
+            auto_pause_after: Some(Duration::from_secs(60)),
+            resume_time: Duration::from_secs(10),
+            state: Mutex::new(PauseState::Running {
+                last_request: start,
+            }),
+        };
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // Each request postpones the pause
+        assert!(pause.request(at(50)).is_ok());
+        assert!(pause.request(at(100)).is_ok());
+
+        // The first request after the idle time wakes the cluster up, which takes a while
+        assert_eq!(pause.current(at(160)), PauseState::Paused);
+        assert_eq!(
+            pause.request(at(170)),
+            Err(PauseState::Resuming { until: at(180) })
+        );
+        assert!(pause.request(at(175)).is_err());
+        assert!(pause.request(at(180)).is_ok());
+        assert!(pause.request(at(200)).is_ok());
+    }
+
+    #[test]
+    fn test_is_active() {
+        let mut clusters = Clusters::default();
+        clusters.add(Cluster {
+            name: "default".to_string(),
+            resource_arn: None,
+            backend: Arc::new(SqliteBackend::in_memory()),
+        });
+        let pauses = Pauses::new(Arc::new(clusters), &Config::default());
+        let now = Instant::now();
+        assert!(!pauses.is_active(now));
+
+        set_state(&pauses, "default", PauseState::Paused);
+        assert!(pauses.is_active(now));
+        set_state(
+            &pauses,
+            "default",
+            PauseState::Running { last_request: now },
+        );
+        assert!(!pauses.is_active(now));
+    }
+}
@@ -1,344 +1,402 @@
 use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
 use axum::http::StatusCode;
-use base64::Engine as _;
-use chrono::{DateTime, Utc};
-use log::{error, info};
-use sqlx::{Column, Row, TypeInfo};
-use sqlx::{
-    Either, Executor, MySql, MySqlPool,
-    mysql::{MySqlArguments, MySqlColumn, MySqlRow},
-    query::Query,
-};
-
-use crate::aws::{FieldDef, SqlParameterDef};
+use chrono::Utc;
+use hashlink::LruCache;
+use log::info;
+use sqlx::Either;
+
+use crate::aws::{ColumnMetadataDef, FieldDef, SqlParameterDef};
+use crate::backend::Transaction;
+use crate::cluster::Cluster;
+use crate::metrics;
+use crate::statements::{LoggedStatement, StatementLog};
 
 const MAX_SQL_LEN: usize = 65536;
 
-pub fn try_row_to_aws_fields(row: MySqlRow) -> Result<Vec<FieldDef>, sqlx::Error> {
-    let columns = row.columns();
-    let mut values = Vec::new();
+/// How many of the last statements run have their rewritten SQL kept, for each dialect
+const REWRITE_CACHE_SIZE: usize = 1000;
+
+/// Longer statements aren't cached, as they're usually generated ones that are seldom run
+/// twice, e.g. bulk inserts with inlined values, and would make the cache hold a lot of memory
+const MAX_CACHED_SQL_LEN: usize = 4096;
+
+/// A statement rewritten for a backend, with the names of the parameters of its placeholders
+type Rewritten = (String, Vec<String>);
+
+type RewriteCache = LruCache<String, Arc<Rewritten>>;
 
-    for column in columns {
-        let field = column_into_fielddef(&row, column).inspect_err(|e| {
-            error!(
-                "Error converting column '{}' to FieldDef: {e}",
-                column.name()
-            );
-        })?;
+/// Rewritten SQL of the statements run recently, so that the statements run over and over
+/// aren't parsed again each time
+static REWRITES: LazyLock<Mutex<HashMap<Dialect, RewriteCache>>> = LazyLock::new(Mutex::default);
 
-        values.push(field);
+/// Either the records returned by a query or the number of rows it affected
+pub type QueryOutput = Either<Records, u64>;
+pub type QueryError = (StatusCode, anyhow::Error);
+
+/// Rows returned by a query, with the metadata of their columns if the backend has it
+#[derive(Debug, Default)]
+pub struct Records {
+    pub rows: Vec<Vec<FieldDef>>,
+    /// Answered to requests with `includeResultMetadata`
+    pub column_metadata: Option<Vec<ColumnMetadataDef>>,
+}
+
+impl From<Vec<Vec<FieldDef>>> for Records {
+    fn from(rows: Vec<Vec<FieldDef>>) -> Self {
+        Self {
+            rows,
+            column_metadata: None,
+        }
     }
+}
 
-    Ok(values)
+/// The SQL dialect of a backend, which decides how its statements are lexed and how
+/// positional parameters are spelled
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Dialect {
+    /// `?` placeholders, `#` comments, backticks and backslash escapes in strings
+    MySql,
+    /// `$1`, `$2`, ... placeholders, dollar-quoted strings and `E'...'` escape strings
+    Postgres,
+    /// `?` placeholders and backticks
+    Sqlite,
+    /// The mock backend: `:name`, left as written so that its rules see the SQL of the request
+    Mock,
 }
 
-fn column_into_fielddef(row: &MySqlRow, column: &MySqlColumn) -> Result<FieldDef, sqlx::Error> {
-    let column_name = column.name();
-    let type_name = column.type_info().name();
+/// What a `Token` of a statement is
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum TokenKind {
+    /// A keyword, identifier or number
+    Word,
+    /// A named parameter, e.g. `:id`
+    Parameter,
+    /// A string or quoted identifier, with its quotes
+    Quoted,
+    /// A comment, without the newline ending a line comment
+    Comment,
+    /// Anything else, e.g. whitespace, an operator or `;`
+    Other,
+}
 
-    let field = match type_name {
-        "VARCHAR" | "CHAR" | "TEXT" | "LONGTEXT" | "MEDIUMTEXT" | "TINYTEXT" => {
-            match row.try_get::<Option<String>, _>(column_name)? {
-                Some(value) => FieldDef::StringValue(value),
-                None => FieldDef::IsNull(true),
-            }
-        }
-        "BOOLEAN" | "BOOL" => match row.try_get::<Option<bool>, _>(column_name)? {
-            Some(value) => FieldDef::BooleanValue(value),
-            None => FieldDef::IsNull(true),
-        },
-        "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "BIGINT" => {
-            match row.try_get::<Option<i64>, _>(column_name)? {
-                Some(value) => FieldDef::LongValue(value),
-                None => FieldDef::IsNull(true),
-            }
-        }
-        "FLOAT" | "DOUBLE" | "DECIMAL" | "NUMERIC" => {
-            match row.try_get::<Option<f64>, _>(column_name)? {
-                Some(value) => FieldDef::DoubleValue(value),
-                None => FieldDef::IsNull(true),
-            }
-        }
-        "DATE" | "DATETIME" | "TIMESTAMP" | "TIME" | "YEAR" => {
-            // Convert temporal types to string representation
-            match row.try_get::<Option<DateTime<Utc>>, _>(column_name)? {
-                Some(value) => FieldDef::StringValue(value.to_string()),
-                None => FieldDef::IsNull(true),
+/// A piece of a statement, as written in it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+}
+
+/// Split `sql` into tokens following the lexical rules of `dialect`, so that what's in
+/// quotes and comments can be told apart from the statement itself. Concatenating the
+/// tokens gives `sql` back.
+pub(crate) fn tokenize(sql: &str, dialect: Dialect) -> impl Iterator<Item = Token<'_>> {
+    let mut position = 0;
+    std::iter::from_fn(move || {
+        let rest = &sql[position..];
+        let first = rest.chars().next()?;
+        let (kind, len) = match first {
+            '\'' | '"' => (
+                TokenKind::Quoted,
+                quoted_len(rest, dialect == Dialect::MySql),
+            ),
+            '`' if matches!(dialect, Dialect::MySql | Dialect::Sqlite) => {
+                (TokenKind::Quoted, quoted_len(rest, false))
             }
-        }
-        "VARBINARY" | "BINARY" | "BLOB" | "LONGBLOB" | "MEDIUMBLOB" | "TINYBLOB" => {
-            match row.try_get::<Option<Vec<u8>>, _>(column_name)? {
-                Some(value) => {
-                    // Encode to base64 string
-                    let value = base64::engine::general_purpose::STANDARD.encode(&value);
-                    FieldDef::BlobValue(value)
+            '#' if dialect == Dialect::MySql => (TokenKind::Comment, line_comment_len(rest)),
+            '-' if rest.starts_with("--") => (TokenKind::Comment, line_comment_len(rest)),
+            '/' if rest.starts_with("/*") => (
+                TokenKind::Comment,
+                rest[2..].find("*/").map_or(rest.len(), |end| end + 4),
+            ),
+            '$' if dialect == Dialect::Postgres => match dollar_quoted_len(rest) {
+                Some(len) => (TokenKind::Quoted, len),
+                None => (TokenKind::Other, 1),
+            },
+            // PostgreSQL type cast (e.g. '1'::int), not a named parameter
+            ':' if rest.starts_with("::") => (TokenKind::Other, 2),
+            ':' if rest[1..].starts_with(|c: char| c == '_' || c.is_ascii_alphabetic()) => (
+                TokenKind::Parameter,
+                1 + rest[1..]
+                    .find(|c: char| !(c == '_' || c.is_ascii_alphanumeric()))
+                    .unwrap_or(rest.len() - 1),
+            ),
+            c if c == '_' || c.is_ascii_alphanumeric() => {
+                let len = rest
+                    .find(|c: char| !(c == '_' || c == '$' || c.is_ascii_alphanumeric()))
+                    .unwrap_or(rest.len());
+                if dialect == Dialect::Postgres
+                    && rest[..len].eq_ignore_ascii_case("e")
+                    && rest[len..].starts_with('\'')
+                {
+                    (TokenKind::Quoted, len + quoted_len(&rest[len..], true))
+                } else {
+                    (TokenKind::Word, len)
                 }
-                None => FieldDef::IsNull(true),
-            }
-        }
-        _ => {
-            info!("Unknown field type for column '{column_name}': {type_name}");
-            // Try to get as string for unknown types
-            match row.try_get::<Option<String>, _>(column_name)? {
-                Some(value) => FieldDef::StringValue(value),
-                None => FieldDef::IsNull(true),
             }
+            c => (TokenKind::Other, c.len_utf8()),
+        };
+        position += len;
+        Some(Token {
+            kind,
+            text: &rest[..len],
+        })
+    })
+}
+
+/// Length of the quoted string or identifier `rest` starts with, where a doubled quote
+/// stands for itself and, if `backslash_escapes`, a backslash escapes the next character
+fn quoted_len(rest: &str, backslash_escapes: bool) -> usize {
+    let quote = rest.chars().next().unwrap_or_default();
+    let mut chars = rest.char_indices().skip(1).peekable();
+    while let Some((index, c)) = chars.next() {
+        if c == '\\' && backslash_escapes {
+            chars.next();
+        } else if c == quote && chars.next_if(|&(_, next)| next == quote).is_none() {
+            return index + 1;
         }
-    };
-    Ok(field)
+    }
+    rest.len()
+}
+
+fn line_comment_len(rest: &str) -> usize {
+    rest.find('\n').unwrap_or(rest.len())
 }
 
-/// Rewrite named parameters (e.g., :id) to positional placeholders ('?') while preserving
-/// all other SQL characters and whitespace exactly. Returns the rewritten SQL and the ordered
-/// list of parameter names.
-fn rewrite_named_params_preserving_sql(sql: &str) -> (String, Vec<String>) {
-    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-    enum State {
-        Normal,
-        Quoted(char), // quote delimiter: '\'', '"', or '`'
-        LineComment,  // -- ... or # ...
-        BlockComment, // /* ... */
+/// Length of the PostgreSQL `$tag$ ... $tag$` string `rest` starts with, if it's one
+fn dollar_quoted_len(rest: &str) -> Option<usize> {
+    let tag_len = 2 + rest[1..].find(|c: char| !(c == '_' || c.is_ascii_alphanumeric()))?;
+    let tag = &rest[..tag_len];
+    if !tag.ends_with('$') || tag[1..].starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
     }
+    // The closing tag is only looked for after the opening one, so that `$$$` isn't closed
+    // by its own first two characters
+    Some(
+        rest[tag_len..]
+            .find(tag)
+            .map_or(rest.len(), |end| tag_len + end + tag_len),
+    )
+}
+
+/// Whether the statement returns records rather than a count of affected rows
+pub fn is_select(sql: &str) -> bool {
+    sql.trim_start().to_ascii_uppercase().starts_with("SELECT")
+}
+
+/// Whether `keyword` appears in `sql` as a word, rather than in a string, a comment or as
+/// part of an identifier
+pub(crate) fn has_keyword(sql: &str, dialect: Dialect, keyword: &str) -> bool {
+    tokenize(sql, dialect)
+        .any(|token| token.kind == TokenKind::Word && token.text.eq_ignore_ascii_case(keyword))
+}
 
+/// Rewrite named parameters (e.g., :id) to positional placeholders ('?' or '$1', depending
+/// on `dialect`) while preserving all other SQL characters and whitespace exactly. Returns the
+/// rewritten SQL and the ordered list of parameter names.
+pub fn rewrite_named_params_preserving_sql(sql: &str, dialect: Dialect) -> (String, Vec<String>) {
     let mut out = String::with_capacity(sql.len());
     let mut args = Vec::<String>::new();
-    let mut state = State::Normal;
-    let mut chars = sql.chars().peekable();
-
-    while let Some(ch) = chars.next() {
-        match state {
-            State::Normal => match ch {
-                '\'' | '"' | '`' => {
-                    out.push(ch);
-                    state = State::Quoted(ch);
-                }
-                '-' => {
-                    if matches!(chars.peek(), Some('-')) {
-                        out.push('-');
-                        out.push('-');
-                        chars.next();
-                        state = State::LineComment;
-                    } else {
-                        out.push('-');
-                    }
-                }
-                '#' => {
-                    out.push('#');
-                    state = State::LineComment;
-                }
-                '/' => {
-                    if matches!(chars.peek(), Some('*')) {
-                        out.push('/');
-                        out.push('*');
-                        chars.next();
-                        state = State::BlockComment;
-                    } else {
-                        out.push('/');
-                    }
-                }
-                ':' => {
-                    // Only treat as a named parameter if next char starts a valid identifier
-                    let mut clone = chars.clone();
-                    if let Some(nc) = clone.peek().copied() {
-                        if nc == '_' || nc.is_ascii_alphabetic() {
-                            // consume identifier
-                            let mut name = String::new();
-                            while let Some(&c) = clone.peek() {
-                                if c == '_' || c.is_ascii_alphanumeric() {
-                                    name.push(c);
-                                    clone.next();
-                                } else {
-                                    break;
-                                }
-                            }
-                            if !name.is_empty() {
-                                // commit consumption
-                                for _ in 0..name.len() {
-                                    chars.next();
-                                }
-                                out.push('?');
-                                args.push(name);
-                                continue;
-                            }
-                        }
-                    }
-                    // Not a named param, just output ':'
-                    out.push(':');
-                }
-                _ => out.push(ch),
-            },
-            State::Quoted(delim) => {
-                out.push(ch);
-                match ch {
-                    // backslash escapes inside quoted strings
-                    '\\' if delim != '`' => {
-                        if let Some(next) = chars.next() {
-                            out.push(next);
-                        }
-                    }
-                    // doubled delimiter ('' or "" or ``) as escape: copy both and stay quoted
-                    _ if ch == delim => {
-                        if matches!(chars.peek(), Some(&c) if c == delim) {
-                            out.push(delim);
-                            chars.next();
-                        } else {
-                            state = State::Normal;
-                        }
-                    }
-                    _ => {}
-                }
-            }
-            State::LineComment => {
-                out.push(ch);
-                if ch == '\n' {
-                    state = State::Normal;
-                }
-            }
-            State::BlockComment => {
-                out.push(ch);
-                if ch == '*' && matches!(chars.peek(), Some('/')) {
-                    out.push('/');
-                    chars.next();
-                    state = State::Normal;
-                }
-            }
+    for token in tokenize(sql, dialect) {
+        if token.kind != TokenKind::Parameter {
+            out.push_str(token.text);
+            continue;
         }
+        match dialect {
+            Dialect::MySql | Dialect::Sqlite => out.push('?'),
+            Dialect::Postgres => out.push_str(&format!("${}", args.len() + 1)),
+            Dialect::Mock => out.push_str(token.text),
+        }
+        args.push(token.text[1..].to_string());
     }
 
     (out, args)
 }
 
-fn bind_parameters<'q>(
-    mut query: Query<'q, MySql, MySqlArguments>,
-    args_to_be_bound: &[&str],
-    params: &'q [SqlParameterDef],
-) -> Result<Query<'q, MySql, MySqlArguments>, anyhow::Error> {
+/// Look up the parameter to bind to each placeholder, in the order they appear in the statement
+pub fn ordered_parameters<'p>(
+    args_to_be_bound: &[String],
+    params: &'p [SqlParameterDef],
+) -> Result<Vec<&'p SqlParameterDef>, anyhow::Error> {
     let params = {
         let mut map = HashMap::new();
         for param in params {
-            if map.insert(param.name.clone(), param).is_some() {
+            if map.insert(param.name.as_str(), param).is_some() {
                 return Err(anyhow!("Duplicate parameter: {}", param.name));
             }
         }
         map
     };
 
-    for argname in args_to_be_bound {
-        let Some(arg) = params.get(*argname) else {
-            return Err(anyhow!("Missing parameter: {argname}"));
-        };
-
-        query = match &arg.value {
-            FieldDef::ArrayValue(_) => {
-                return Err(anyhow!("Array parameters are not supported"));
-            }
-            FieldDef::BlobValue(b64) => {
-                let data = base64::engine::general_purpose::STANDARD
-                    .decode(b64)
-                    .map_err(|e| {
-                        anyhow!(
-                            "Failed to decode base64 blob for parameter '{}': {e}",
-                            arg.name
-                        )
-                    })?;
-                query.bind(data)
-            }
-            FieldDef::BooleanValue(x) => query.bind(*x),
-            FieldDef::DoubleValue(x) => query.bind(*x),
-            FieldDef::IsNull(_) => query.bind(None::<String>),
-            FieldDef::LongValue(x) => query.bind(*x),
-            FieldDef::StringValue(x) => query.bind(x.as_str()),
-        }
-    }
-
-    Ok(query)
+    args_to_be_bound
+        .iter()
+        .map(|argname| {
+            params
+                .get(argname.as_str())
+                .copied()
+                .ok_or_else(|| anyhow!("Missing parameter: {argname}"))
+        })
+        .collect()
 }
 
-pub async fn run_query(
-    pool: &MySqlPool,
+pub(crate) async fn run_query(
+    cluster: &Cluster,
     database: Option<String>,
     schema: Option<String>,
     sql: &str,
     params: Vec<Vec<SqlParameterDef>>,
-) -> Result<Either<Vec<Vec<FieldDef>>, u64>, (StatusCode, anyhow::Error)> {
-    let _ = params;
-    if sql.len() > MAX_SQL_LEN {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            anyhow::anyhow!("SQL statement exceeds maximum length"),
-        ));
-    }
+    log: &StatementLog,
+) -> Result<QueryOutput, QueryError> {
+    let rewritten = prepare(cluster, sql, &params)?;
+    let (prepared_sql, args_to_be_bound) = &*rewritten;
+    let logged = start_logging(
+        log,
+        cluster,
+        database.clone(),
+        None,
+        sql,
+        prepared_sql,
+        &params,
+    );
+
+    let start = Instant::now();
+    let result = cluster
+        .backend
+        .run_query(
+            database.as_deref(),
+            schema.as_deref(),
+            prepared_sql,
+            args_to_be_bound,
+            params,
+        )
+        .await;
+    observe(cluster, log, logged, start.elapsed(), &result);
+    result
+}
+
+/// Like `run_query`, but in `transaction`, started on `cluster` and `database`
+pub(crate) async fn run_query_in_transaction(
+    cluster: &Cluster,
+    transaction: &mut dyn Transaction,
+    transaction_id: &str,
+    database: Option<String>,
+    sql: &str,
+    params: Vec<Vec<SqlParameterDef>>,
+    log: &StatementLog,
+) -> Result<QueryOutput, QueryError> {
+    let rewritten = prepare(cluster, sql, &params)?;
+    let (prepared_sql, args_to_be_bound) = &*rewritten;
+    let logged = start_logging(
+        log,
+        cluster,
+        database,
+        Some(transaction_id),
+        sql,
+        prepared_sql,
+        &params,
+    );
+
+    let start = Instant::now();
+    let result = transaction
+        .run_query(prepared_sql, args_to_be_bound, params)
+        .await;
+    observe(cluster, log, logged, start.elapsed(), &result);
+    result
+}
 
-    // Use the same connection for all queries, because otherwise the "USE database"
-    // command might not apply to the subsequent queries
-    let mut conn = pool
-        .acquire()
-        .await
-        .inspect_err(|e| error!("Failed to acquire a database connection: {e:?}"))
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
-
-    if let Some(database) = &database {
-        conn.execute(sqlx::raw_sql(&format!("USE {database}")))
-            .await
-            .inspect_err(|e| error!("Failed to select database '{database}': {e:?}"))
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+/// The entry of the statement log for a statement about to run, if the log is enabled
+fn start_logging(
+    log: &StatementLog,
+    cluster: &Cluster,
+    database: Option<String>,
+    transaction_id: Option<&str>,
+    sql: &str,
+    prepared_sql: &str,
+    params: &[Vec<SqlParameterDef>],
+) -> Option<LoggedStatement> {
+    log.is_enabled().then(|| LoggedStatement {
+        id: 0,
+        timestamp: Utc::now(),
+        cluster: cluster.name.clone(),
+        database,
+        transaction_id: transaction_id.map(str::to_string),
+        sql: sql.to_string(),
+        rewritten_sql: prepared_sql.to_string(),
+        parameters: serde_json::to_value(params).unwrap_or_default(),
+        rows_returned: None,
+        rows_affected: None,
+        duration_ms: 0.0,
+        error: None,
+    })
+}
+
+/// Count the outcome of a statement in the metrics, and add it to the statement log
+fn observe(
+    cluster: &Cluster,
+    log: &StatementLog,
+    logged: Option<LoggedStatement>,
+    duration: Duration,
+    result: &Result<QueryOutput, QueryError>,
+) {
+    metrics::observe_query(&cluster.name, duration, result);
+
+    let Some(mut logged) = logged else {
+        return;
+    };
+    logged.duration_ms = duration.as_secs_f64() * 1000.0;
+    match result {
+        Ok(Either::Left(records)) => logged.rows_returned = Some(records.rows.len()),
+        Ok(Either::Right(affected_rows)) => logged.rows_affected = Some(*affected_rows),
+        Err((_, e)) => logged.error = Some(e.to_string()),
     }
+    log.record(logged);
+}
 
-    if schema.is_some() {
+/// Check `sql` and rewrite its named parameters for the backend of `cluster`
+fn prepare(
+    cluster: &Cluster,
+    sql: &str,
+    params: &[Vec<SqlParameterDef>],
+) -> Result<Arc<Rewritten>, QueryError> {
+    if sql.len() > MAX_SQL_LEN {
         return Err((
-            StatusCode::NOT_IMPLEMENTED,
-            anyhow!("Schema selection is not supported"),
+            StatusCode::BAD_REQUEST,
+            anyhow!("SQL statement exceeds maximum length"),
         ));
     }
 
-    let (prepared_sql, args_to_be_bound) = rewrite_named_params_preserving_sql(sql);
-    info!("Running '{prepared_sql}' with {} parameters", params.len());
-
-    let value = if sql.trim_start().to_ascii_uppercase().starts_with("SELECT") {
-        let mut collected_records = vec![];
-
-        for row_params in params {
-            let query = sqlx::query(&prepared_sql);
-            let arg_refs: Vec<&str> = args_to_be_bound.iter().map(|s| s.as_str()).collect();
-            let query = bind_parameters(query, &arg_refs, &row_params)
-                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
-
-            let records = query
-                .fetch_all(&mut *conn)
-                .await
-                .inspect_err(|e| error!("Failed to execute query: {e:?}"))
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
-
-            collected_records.extend(
-                records
-                    .into_iter()
-                    .filter_map(|row| try_row_to_aws_fields(row).ok()),
-            );
-        }
+    let rewritten = rewrite(sql, cluster.backend.dialect());
+    info!("Running '{}' with {} parameters", rewritten.0, params.len());
+    Ok(rewritten)
+}
 
-        Either::Left(collected_records)
-    } else {
-        let mut affected_rows = 0;
-        for row_params in params {
-            let query = sqlx::query(&prepared_sql);
-            let arg_refs: Vec<&str> = args_to_be_bound.iter().map(|s| s.as_str()).collect();
-            let query = bind_parameters(query, &arg_refs, &row_params)
-                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
-
-            affected_rows += query
-                .execute(&mut *conn)
-                .await
-                .inspect_err(|e| error!("Failed to execute query: {e:?}"))
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.into()))?
-                .rows_affected();
-        }
+/// `sql` rewritten for `dialect`, from the cache if it was run recently
+fn rewrite(sql: &str, dialect: Dialect) -> Arc<Rewritten> {
+    if sql.len() > MAX_CACHED_SQL_LEN {
+        return Arc::new(rewrite_named_params_preserving_sql(sql, dialect));
+    }
 
-        Either::Right(affected_rows)
-    };
+    let cached = REWRITES
+        .lock()
+        .unwrap()
+        .get_mut(&dialect)
+        .and_then(|cache| cache.get(sql).cloned());
+    metrics::rewrite_cache_lookup(cached.is_some());
+    if let Some(rewritten) = cached {
+        return rewritten;
+    }
 
-    Ok(value)
+    let rewritten = Arc::new(rewrite_named_params_preserving_sql(sql, dialect));
+    REWRITES
+        .lock()
+        .unwrap()
+        .entry(dialect)
+        .or_insert_with(|| LruCache::new(REWRITE_CACHE_SIZE))
+        .insert(sql.to_string(), rewritten.clone());
+    rewritten
 }
 
 #[cfg(test)]
@@ -348,7 +406,7 @@ mod tests {
     #[test]
     fn test_rewrite_named_params_simple() {
         let sql = "SELECT * FROM t WHERE id = :id AND name = :name";
-        let (rewritten, args) = rewrite_named_params_preserving_sql(sql);
+        let (rewritten, args) = rewrite_named_params_preserving_sql(sql, Dialect::MySql);
         assert_eq!(rewritten, "SELECT * FROM t WHERE id = ? AND name = ?");
         assert_eq!(args, vec!["id", "name"]);
     }
@@ -356,7 +414,7 @@ mod tests {
     #[test]
     fn test_rewrite_named_params_colon_in_string() {
         let sql = r#"SELECT ':notparam' AS s, col FROM t WHERE a = :a"#;
-        let (rewritten, args) = rewrite_named_params_preserving_sql(sql);
+        let (rewritten, args) = rewrite_named_params_preserving_sql(sql, Dialect::MySql);
         assert_eq!(
             rewritten,
             r#"SELECT ':notparam' AS s, col FROM t WHERE a = ?"#
@@ -367,7 +425,7 @@ mod tests {
     #[test]
     fn test_rewrite_named_params_comments() {
         let sql = "-- :skip one\nSELECT :x /* :skip two */ , :y # :skip three\nFROM t";
-        let (rewritten, args) = rewrite_named_params_preserving_sql(sql);
+        let (rewritten, args) = rewrite_named_params_preserving_sql(sql, Dialect::MySql);
         assert_eq!(
             rewritten,
             "-- :skip one\nSELECT ? /* :skip two */ , ? # :skip three\nFROM t"
@@ -378,7 +436,7 @@ mod tests {
     #[test]
     fn test_rewrite_named_params_mysql_literals_preserved() {
         let sql = "INSERT INTO t (a,b,c,d) VALUES (x'1234', b'1010', _utf8mb4'hé', :p)";
-        let (rewritten, args) = rewrite_named_params_preserving_sql(sql);
+        let (rewritten, args) = rewrite_named_params_preserving_sql(sql, Dialect::MySql);
         assert!(rewritten.contains("x'1234', b'1010', _utf8mb4'hé'"));
         assert!(rewritten.ends_with(", ?)"));
         assert_eq!(args, vec!["p"]);
@@ -387,8 +445,104 @@ mod tests {
     #[test]
     fn test_rewrite_named_params_non_identifier_after_colon() {
         let sql = "SELECT ':' AS c, :1 AS not_param";
-        let (rewritten, args) = rewrite_named_params_preserving_sql(sql);
+        let (rewritten, args) = rewrite_named_params_preserving_sql(sql, Dialect::MySql);
         assert_eq!(rewritten, "SELECT ':' AS c, :1 AS not_param");
         assert!(args.is_empty());
     }
+
+    #[test]
+    fn test_rewrite_named_params_numbered() {
+        let sql = "UPDATE t SET a = :a WHERE id = :id OR parent = :id";
+        let (rewritten, args) = rewrite_named_params_preserving_sql(sql, Dialect::Postgres);
+        assert_eq!(
+            rewritten,
+            "UPDATE t SET a = $1 WHERE id = $2 OR parent = $3"
+        );
+        assert_eq!(args, vec!["a", "id", "id"]);
+    }
+
+    #[test]
+    fn test_rewrite_named_params_postgres_cast() {
+        let sql = "SELECT :id::uuid, '{}'::jsonb";
+        let (rewritten, args) = rewrite_named_params_preserving_sql(sql, Dialect::Postgres);
+        assert_eq!(rewritten, "SELECT $1::uuid, '{}'::jsonb");
+        assert_eq!(args, vec!["id"]);
+    }
+
+    #[test]
+    fn test_rewrite_named_params_dollar_quoted() {
+        let sql = "SELECT $$ :a $$, $fn$ it's :b $fn$, a$b, :c";
+        let (rewritten, args) = rewrite_named_params_preserving_sql(sql, Dialect::Postgres);
+        assert_eq!(rewritten, "SELECT $$ :a $$, $fn$ it's :b $fn$, a$b, $1");
+        assert_eq!(args, vec!["c"]);
+    }
+
+    #[test]
+    fn test_rewrite_named_params_dialects() {
+        // `#` is an operator in PostgreSQL, a comment in MySQL
+        let sql = "SELECT data #> '{a,b}' FROM t WHERE id = :id";
+        let (rewritten, args) = rewrite_named_params_preserving_sql(sql, Dialect::Postgres);
+        assert_eq!(rewritten, "SELECT data #> '{a,b}' FROM t WHERE id = $1");
+        assert_eq!(args, vec!["id"]);
+        let (_, args) = rewrite_named_params_preserving_sql(sql, Dialect::MySql);
+        assert!(args.is_empty());
+
+        // Backslashes only escape in MySQL strings and PostgreSQL E'...' strings
+        let sql = r"SELECT 'C:\' AS p, :a";
+        for dialect in [Dialect::Postgres, Dialect::Sqlite] {
+            let (rewritten, args) = rewrite_named_params_preserving_sql(sql, dialect);
+            assert_eq!(args, vec!["a"], "{rewritten}");
+        }
+        let sql = r"SELECT 'it\'s :b', :a";
+        let (_, args) = rewrite_named_params_preserving_sql(sql, Dialect::MySql);
+        assert_eq!(args, vec!["a"]);
+        let sql = r"SELECT E'it\'s :b', :a";
+        let (_, args) = rewrite_named_params_preserving_sql(sql, Dialect::Postgres);
+        assert_eq!(args, vec!["a"]);
+
+        // Dollar quotes are PostgreSQL's only
+        let sql = "SELECT $$ :a $$";
+        let (rewritten, _) = rewrite_named_params_preserving_sql(sql, Dialect::MySql);
+        assert_eq!(rewritten, "SELECT $$ ? $$");
+    }
+
+    #[test]
+    fn test_rewrite_named_params_dollar_quoted_dollar() {
+        let sql = "SELECT $$$:a$$, :b";
+        let (rewritten, args) = rewrite_named_params_preserving_sql(sql, Dialect::Postgres);
+        assert_eq!(rewritten, "SELECT $$$:a$$, $1");
+        assert_eq!(args, vec!["b"]);
+    }
+
+    #[test]
+    fn test_has_keyword() {
+        let returning = |sql| has_keyword(sql, Dialect::Postgres, "RETURNING");
+        assert!(returning("INSERT INTO t (x) VALUES (1) returning id"));
+        assert!(!returning("UPDATE t SET returning_flag = true"));
+        assert!(!returning("UPDATE t SET s = 'RETURNING' -- RETURNING"));
+        assert!(!returning(
+            r#"UPDATE t SET "returning" = 1 /* RETURNING */"#
+        ));
+    }
+
+    #[test]
+    fn test_rewrite_cache() {
+        let sql = "SELECT * FROM test_rewrite_cache WHERE id = :id";
+        let first = rewrite(sql, Dialect::Postgres);
+        assert_eq!(first.0, "SELECT * FROM test_rewrite_cache WHERE id = $1");
+        assert!(Arc::ptr_eq(&first, &rewrite(sql, Dialect::Postgres)));
+
+        let long = format!("SELECT :a{}", " ".repeat(MAX_CACHED_SQL_LEN));
+        assert!(!Arc::ptr_eq(
+            &rewrite(&long, Dialect::Postgres),
+            &rewrite(&long, Dialect::Postgres)
+        ));
+
+        let other_dialect = rewrite(sql, Dialect::MySql);
+        assert_eq!(
+            other_dialect.0,
+            "SELECT * FROM test_rewrite_cache WHERE id = ?"
+        );
+        assert_eq!(other_dialect.1, vec!["id"]);
+    }
 }
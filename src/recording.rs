@@ -0,0 +1,204 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::api::{buffer_body, request_action};
+use crate::error::ERROR_TYPE_HEADER;
+
+/// A Data API request and the response it got, as a line of a recording
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedExchange {
+    pub timestamp: DateTime<Utc>,
+    pub action: String,
+    pub request: serde_json::Value,
+    pub status: u16,
+    /// Body of a successful response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<serde_json::Value>,
+    /// Set instead of `response` if the request failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RecordedErrorDef>,
+    pub latency_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedErrorDef {
+    /// AWS error type, e.g. `BadRequestException`, if the response had one
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub error_type: Option<String>,
+    pub message: String,
+}
+
+/// Appends every Data API exchange to a JSON Lines file
+pub struct Recorder {
+    file: Mutex<tokio::fs::File>,
+}
+
+impl Recorder {
+    /// Open `path` for appending, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open recording file {}", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(tokio::fs::File::from_std(file)),
+        })
+    }
+
+    async fn append(&self, exchange: &RecordedExchange) -> Result<()> {
+        let mut line = serde_json::to_vec(exchange)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// Bodies are kept as JSON when they are, and as a string otherwise
+fn body_to_json(body: &Bytes) -> serde_json::Value {
+    serde_json::from_slice(body)
+        .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(body).into()))
+}
+
+/// Middleware recording the requests to the Data API routes, and their responses
+pub(crate) async fn record(
+    State(recorder): State<Arc<Recorder>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let action = request_action(&request).to_string();
+
+    let timestamp = Utc::now();
+    let start = Instant::now();
+
+    let (request, request_body) = match buffer_body(request).await {
+        Ok(buffered) => buffered,
+        Err(response) => return response,
+    };
+
+    let response = next.run(request).await;
+    let (parts, body) = response.into_parts();
+    let response_body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    let status = parts.status;
+    let (response, error) = if status.is_success() {
+        (Some(body_to_json(&response_body)), None)
+    } else {
+        let error_type = parts
+            .headers
+            .get(ERROR_TYPE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let message = match body_to_json(&response_body) {
+            serde_json::Value::Object(mut object) => match object.remove("message") {
+                Some(serde_json::Value::String(message)) => message,
+                _ => String::from_utf8_lossy(&response_body).into(),
+            },
+            _ => String::from_utf8_lossy(&response_body).into(),
+        };
+        (
+            None,
+            Some(RecordedErrorDef {
+                error_type,
+                message,
+            }),
+        )
+    };
+
+    let exchange = RecordedExchange {
+        timestamp,
+        action,
+        request: body_to_json(&request_body),
+        status: status.as_u16(),
+        response,
+        error,
+        latency_ms,
+    };
+    if let Err(e) = recorder.append(&exchange).await {
+        error!("Failed to record request: {e:#}");
+    }
+
+    Response::from_parts(parts, Body::from(response_body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    use crate::Builder;
+    use crate::backend::SqliteBackend;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn test_recording() {
+        let path =
+            std::env::temp_dir().join(format!("rds-lite-recording-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let app = Builder::new()
+            .config(Config {
+                record_path: Some(path.clone()),
+                ..Config::default()
+            })
+            .backend(SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+
+        for body in [
+            r#"{"sql": "SELECT :x", "parameters": [{"name": "x", "value": {"longValue": 7}}]}"#,
+            r#"{"sql": "SELECT :x"}"#,
+        ] {
+            let request = Request::post("/Execute")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let exchanges: Vec<RecordedExchange> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(exchanges.len(), 2);
+
+        assert_eq!(exchanges[0].action, "ExecuteStatement");
+        assert_eq!(exchanges[0].status, StatusCode::OK.as_u16());
+        assert_eq!(exchanges[0].request["sql"], "SELECT :x");
+        assert_eq!(
+            exchanges[0].response.as_ref().unwrap()["records"],
+            serde_json::json!([[{"longValue": 7}]])
+        );
+
+        assert_eq!(exchanges[1].status, StatusCode::BAD_REQUEST.as_u16());
+        assert_eq!(
+            exchanges[1].error.as_ref().unwrap().message,
+            "Missing parameter: x"
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use axum::{
+    Json, Router,
+    extract::{MatchedPath, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use log::{info, warn};
+use serde_json::{Value, json};
+
+use crate::api::action_name;
+use crate::config::ReplayMatch;
+use crate::error::AwsError;
+use crate::recording::RecordedExchange;
+
+/// Answers Data API requests from a recording made in recording mode. The exchanges recorded
+/// for a request are answered in the order they were recorded, whether they're found by an
+/// exact or a normalized match. Once they have all been used, the last one keeps being answered.
+pub struct Replayer {
+    mode: ReplayMatch,
+    exchanges: Vec<Arc<RecordedExchange>>,
+    /// Indexes into `exchanges` of the ones recorded for each request, in recorded order
+    exact: HashMap<String, Vec<usize>>,
+    normalized: HashMap<String, Vec<usize>>,
+    /// Whether each exchange has been answered already
+    used: Mutex<Vec<bool>>,
+}
+
+impl Replayer {
+    /// Read the JSON Lines recording at `path`
+    pub fn load(path: &Path, mode: ReplayMatch) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read recording {}", path.display()))?;
+
+        let mut exchanges = vec![];
+        let mut exact: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut normalized: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exchange: RecordedExchange = serde_json::from_str(line).with_context(|| {
+                format!("Invalid recording {} line {}", path.display(), index + 1)
+            })?;
+            for (map, normalize) in [(&mut exact, false), (&mut normalized, true)] {
+                map.entry(request_key(&exchange.action, &exchange.request, normalize))
+                    .or_default()
+                    .push(exchanges.len());
+            }
+            exchanges.push(Arc::new(exchange));
+        }
+        info!(
+            "Replaying {} recorded requests from {}",
+            exchanges.len(),
+            path.display()
+        );
+
+        Ok(Self {
+            mode,
+            used: Mutex::new(vec![false; exchanges.len()]),
+            exchanges,
+            exact,
+            normalized,
+        })
+    }
+
+    /// The recorded answer to `request`, preferring an exact match in normalized mode
+    fn find(&self, action: &str, request: &Value) -> Option<Arc<RecordedExchange>> {
+        let candidates = match (
+            self.exact.get(&request_key(action, request, false)),
+            self.mode,
+        ) {
+            (Some(candidates), _) => candidates,
+            (None, ReplayMatch::Exact) => return None,
+            (None, ReplayMatch::Normalized) => {
+                self.normalized.get(&request_key(action, request, true))?
+            }
+        };
+
+        let mut used = self.used.lock().unwrap();
+        let index = candidates
+            .iter()
+            .copied()
+            .find(|&index| !used[index])
+            .or(candidates.last().copied())?;
+        used[index] = true;
+        Some(self.exchanges[index].clone())
+    }
+}
+
+/// What a request is matched on: its action, database, schema, SQL and parameters.
+/// The ARNs are left out as they usually differ between environments.
+fn request_key(action: &str, request: &Value, normalize: bool) -> String {
+    let sql = request["sql"].as_str().unwrap_or_default();
+    let parameters = match request.get("parameters") {
+        Some(parameters) => parameters,
+        None => &request["parameterSets"],
+    };
+
+    let (sql, parameters) = if normalize {
+        (normalize_sql(sql), normalize_parameters(parameters))
+    } else {
+        (sql.to_string(), parameters.clone())
+    };
+    json!([
+        action,
+        request["database"],
+        request["schema"],
+        sql,
+        parameters
+    ])
+    .to_string()
+}
+
+/// Drop comments and the whitespace that doesn't separate words, lowercase everything but
+/// quoted identifiers and replace string and numeric literals with `?`, so that
+/// `SELECT * FROM t WHERE id = 1` matches `select * from t where id=2`
+fn normalize_sql(sql: &str) -> String {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut normalized = String::with_capacity(sql.len());
+    let mut pending_space = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        // Whitespace and comments
+        if c.is_whitespace() {
+            pending_space = true;
+            i += 1;
+            continue;
+        }
+        if c == '-' && next == Some('-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            pending_space = true;
+            continue;
+        }
+        if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+            pending_space = true;
+            continue;
+        }
+
+        // A space is only needed between two words, e.g. not in `id = 1` vs `id=1`
+        let previous = normalized.chars().last();
+        if pending_space
+            && previous.is_some_and(is_word_char)
+            && (is_word_char(c) || c == '\'' || c == '"' || c == '`')
+        {
+            normalized.push(' ');
+        }
+        pending_space = false;
+        let previous = normalized.chars().last();
+
+        match c {
+            '\'' => {
+                i += 1;
+                while i < chars.len() {
+                    if chars[i] == '\'' {
+                        // '' is an escaped quote
+                        if chars.get(i + 1) == Some(&'\'') {
+                            i += 2;
+                            continue;
+                        }
+                        break;
+                    }
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+                normalized.push('?');
+            }
+            '"' | '`' => {
+                // Quoted identifiers are kept as they are
+                normalized.push(c);
+                i += 1;
+                while i < chars.len() && chars[i] != c {
+                    normalized.push(chars[i]);
+                    i += 1;
+                }
+                if i < chars.len() {
+                    normalized.push(c);
+                }
+                i += 1;
+            }
+            c if c.is_ascii_digit() && !previous.is_some_and(|p| is_word_char(p) || p == ':') => {
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                    i += 1;
+                }
+                normalized.push('?');
+            }
+            c => {
+                normalized.extend(c.to_lowercase());
+                i += 1;
+            }
+        }
+    }
+
+    normalized.trim_end_matches(';').to_string()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || c == '?'
+}
+
+/// Sort parameters by name and drop their type hints, recursing into parameter sets
+fn normalize_parameters(parameters: &Value) -> Value {
+    match parameters {
+        Value::Array(items) if items.iter().all(Value::is_array) => {
+            Value::Array(items.iter().map(normalize_parameters).collect())
+        }
+        Value::Array(items) => {
+            let mut items: Vec<Value> = items
+                .iter()
+                .map(|item| {
+                    let mut item = item.clone();
+                    if let Some(object) = item.as_object_mut() {
+                        object.remove("typeHint");
+                    }
+                    item
+                })
+                .collect();
+            items.sort_by_key(|item| item["name"].as_str().unwrap_or_default().to_string());
+            Value::Array(items)
+        }
+        other => other.clone(),
+    }
+}
+
+/// Send back what was recorded, success or error
+fn replay_response(exchange: &RecordedExchange) -> Response {
+    let status = StatusCode::from_u16(exchange.status).unwrap_or(StatusCode::OK);
+    match (&exchange.response, &exchange.error) {
+        (Some(body), _) => (status, Json(body.clone())).into_response(),
+        (None, Some(error)) => match &error.error_type {
+            Some(error_type) => AwsError {
+                status,
+                error_type: error_type.clone(),
+                message: error.message.clone(),
+            }
+            .into_response(),
+            None => (status, error.message.clone()).into_response(),
+        },
+        (None, None) => status.into_response(),
+    }
+}
+
+async fn replay(
+    State(replayer): State<Arc<Replayer>>,
+    path: MatchedPath,
+    Json(request): Json<Value>,
+) -> Response {
+    let action = action_name(path.as_str()).unwrap_or("Unknown");
+    match replayer.find(action, &request) {
+        Some(exchange) => replay_response(&exchange),
+        None => {
+            let sql = request["sql"].as_str().unwrap_or_default();
+            warn!("No recorded response for {action}: {sql}");
+            AwsError::new(
+                "BadRequestException",
+                format!(
+                    "No recorded response matches this {action} request, with SQL '{sql}' and its parameters"
+                ),
+            )
+            .into_response()
+        }
+    }
+}
+
+/// Routes of the Data API, answered from `replayer`
+pub(crate) fn router(replayer: Arc<Replayer>) -> Router {
+    Router::new()
+        .route("/Execute", post(replay))
+        .route("/BatchExecute", post(replay))
+        .route("/BeginTransaction", post(replay))
+        .route("/CommitTransaction", post(replay))
+        .route("/RollbackTransaction", post(replay))
+        .with_state(replayer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_normalize_sql() {
+        assert_eq!(
+            normalize_sql("SELECT *\n  FROM t -- all of them\n WHERE id = 12 AND name='it''s';"),
+            "select*from t where id=? and name=?"
+        );
+        assert_eq!(
+            normalize_sql("select * from t where id=7 and name = 'x'"),
+            "select*from t where id=? and name=?"
+        );
+        assert_eq!(
+            normalize_sql(r#"SELECT "Col1", c2 FROM t2 WHERE x = :p1 /* c */ LIMIT 10"#),
+            r#"select "Col1",c2 from t2 where x=:p1 limit ?"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let path =
+            std::env::temp_dir().join(format!("rds-lite-replay-{}.jsonl", std::process::id()));
+        let lines = [
+            json!({"timestamp": "2026-01-01T00:00:00Z", "action": "ExecuteStatement", "request": {"sql": "SELECT count(*) FROM t"}, "status": 200, "response": {"records": [[{"longValue": 1}]]}, "latencyMs": 1.0}),
+            json!({"timestamp": "2026-01-01T00:00:01Z", "action": "ExecuteStatement", "request": {"sql": "SELECT count(*) FROM t"}, "status": 200, "response": {"records": [[{"longValue": 2}]]}, "latencyMs": 1.0}),
+            json!({"timestamp": "2026-01-01T00:00:02Z", "action": "ExecuteStatement", "request": {"sql": "SELECT name FROM t WHERE id = :id", "parameters": [{"name": "id", "value": {"longValue": 1}}]}, "status": 200, "response": {"records": [[{"stringValue": "one"}]]}, "latencyMs": 1.0}),
+            json!({"timestamp": "2026-01-01T00:00:03Z", "action": "ExecuteStatement", "request": {"sql": "SELECT nope"}, "status": 400, "error": {"type": "BadRequestException", "message": "Unknown column"}, "latencyMs": 1.0}),
+        ];
+        let contents: Vec<String> = lines.iter().map(Value::to_string).collect();
+        std::fs::write(&path, contents.join("\n")).unwrap();
+        let replayer = Replayer::load(&path, ReplayMatch::Normalized).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let app = router(Arc::new(replayer));
+
+        let call = |body: Value| {
+            let app = app.clone();
+            async move {
+                let request = Request::post("/Execute")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let error_type = response.headers().get("x-amzn-ErrorType").cloned();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    error_type,
+                    String::from_utf8(bytes.to_vec()).unwrap(),
+                )
+            }
+        };
+
+        // Recorded responses are answered in order, then the last one repeats, whether they
+        // match exactly or once normalized
+        let sqls = [
+            "select COUNT(*)  from t;",
+            "SELECT count(*) FROM t",
+            "select COUNT(*)  from t;",
+        ];
+        for (sql, expected) in sqls.into_iter().zip([1, 2, 2]) {
+            let (status, _, body) = call(json!({ "sql": sql })).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(
+                body,
+                format!(r#"{{"records":[[{{"longValue":{expected}}}]]}}"#)
+            );
+        }
+
+        let (status, _, body) = call(json!({
+            "resourceArn": "arn:other",
+            "sql": "SELECT name FROM t WHERE id = :id",
+            "parameters": [{"name": "id", "value": {"longValue": 1}, "typeHint": "DECIMAL"}],
+        }))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("one"));
+
+        let (status, error_type, body) = call(json!({"sql": "SELECT nope"})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error_type.unwrap(), "BadRequestException");
+        assert_eq!(body, r#"{"message":"Unknown column"}"#);
+
+        let (status, error_type, body) = call(json!({
+            "sql": "SELECT name FROM t WHERE id = :id",
+            "parameters": [{"name": "id", "value": {"longValue": 2}}],
+        }))
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error_type.unwrap(), "BadRequestException");
+        assert!(body.contains("No recorded response"), "{body}");
+    }
+}
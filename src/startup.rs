@@ -0,0 +1,154 @@
+//! Waiting for the databases to accept connections at startup, e.g. when they're started
+//! alongside rds-lite by docker-compose
+
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use futures_util::future;
+use log::info;
+use sqlx::mysql::MySqlDatabaseError;
+use tokio::time::Instant;
+
+use crate::cluster::{Cluster, Clusters};
+use crate::config::WaitConfig;
+
+/// Try connecting to every cluster until they all accept connections. Fails as soon as a
+/// cluster rejects the connection for a reason waiting won't fix, e.g. a wrong password, or
+/// once `config.timeout_ms` has passed.
+pub(crate) async fn wait_for_databases(clusters: &Clusters, config: &WaitConfig) -> Result<()> {
+    let deadline = Instant::now() + Duration::from_millis(config.timeout_ms);
+    let waits = clusters
+        .iter()
+        .map(|cluster| wait_for(cluster, config, deadline));
+    future::try_join_all(waits).await?;
+    Ok(())
+}
+
+async fn wait_for(cluster: &Cluster, config: &WaitConfig, deadline: Instant) -> Result<()> {
+    let start = Instant::now();
+    let mut backoff = Duration::from_millis(config.initial_backoff_ms);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let attempt_timeout = remaining.max(Duration::from_millis(config.initial_backoff_ms));
+        let error =
+            match tokio::time::timeout(attempt_timeout, cluster.backend.check_connection()).await {
+                Ok(Ok(())) => {
+                    if attempt > 1 {
+                        info!(
+                            "Cluster '{}' accepts connections after {}ms",
+                            cluster.name,
+                            start.elapsed().as_millis()
+                        );
+                    }
+                    return Ok(());
+                }
+                Ok(Err(e)) => e,
+                Err(_) => anyhow!("Timed out after {}ms", attempt_timeout.as_millis()),
+            };
+
+        if let Some(reason) = permanent_failure(&error) {
+            bail!(
+                "Cannot connect to cluster '{}', {reason}: {error}",
+                cluster.name
+            );
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            bail!(
+                "Cluster '{}' is still unreachable after {attempt} attempts in {}ms: {error}",
+                cluster.name,
+                start.elapsed().as_millis()
+            );
+        }
+        let delay = backoff.min(remaining);
+        info!(
+            "Waiting for cluster '{}', attempt {attempt} failed, retrying in {}ms: {error}",
+            cluster.name,
+            delay.as_millis()
+        );
+        tokio::time::sleep(delay).await;
+        backoff = (backoff * 2).min(Duration::from_millis(config.max_backoff_ms));
+    }
+}
+
+/// Why connecting failed, if retrying can't help: the database is up but refuses the
+/// connection, or the URL itself is wrong
+fn permanent_failure(error: &anyhow::Error) -> Option<&'static str> {
+    match error.downcast_ref::<sqlx::Error>()? {
+        sqlx::Error::Database(e) => {
+            let mysql_number = e
+                .try_downcast_ref::<MySqlDatabaseError>()
+                .map(MySqlDatabaseError::number);
+            match (e.code().as_deref(), mysql_number) {
+                // Access denied, and PostgreSQL's invalid password and rejected connection
+                (_, Some(1044 | 1045 | 1698)) | (Some("28P01" | "28000"), None) => {
+                    Some("authentication failed, check the user and password of its database_url")
+                }
+                (_, Some(1049)) | (Some("3D000"), None) => {
+                    Some("the database of its database_url doesn't exist")
+                }
+                _ => None,
+            }
+        }
+        sqlx::Error::Tls(_) => Some("the TLS negotiation failed"),
+        sqlx::Error::Configuration(_) => Some("its database_url is invalid"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use crate::config::PoolConfig;
+
+    fn clusters(urls: &[&str]) -> Clusters {
+        let mut clusters = Clusters::default();
+        for (index, url) in urls.iter().enumerate() {
+            clusters.add(Cluster {
+                name: format!("cluster{index}"),
+                resource_arn: Some(format!("arn:{index}")),
+                backend: backend::connect_lazy(url, &PoolConfig::default()).unwrap(),
+            });
+        }
+        clusters
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_databases() {
+        let config = WaitConfig {
+            timeout_ms: 300,
+            initial_backoff_ms: 50,
+            max_backoff_ms: 100,
+        };
+        let available = clusters(&["sqlite::memory:", "sqlite::memory:"]);
+        wait_for_databases(&available, &config).await.unwrap();
+
+        // Nothing listens on port 1
+        let start = Instant::now();
+        let unreachable = clusters(&["sqlite::memory:", "mysql://root@127.0.0.1:1"]);
+        let error = wait_for_databases(&unreachable, &config)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(
+            error.starts_with("Cluster 'cluster1' is still unreachable after"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_permanent_failure() {
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert_eq!(permanent_failure(&sqlx::Error::Io(refused).into()), None);
+        assert_eq!(permanent_failure(&anyhow!("Timed out")), None);
+        let invalid = sqlx::Error::Configuration("invalid port".into());
+        assert_eq!(
+            permanent_failure(&invalid.into()),
+            Some("its database_url is invalid")
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::StatusCode,
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A statement run on a cluster, as listed by `/_admin/statements`
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct LoggedStatement {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub cluster: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+    /// SQL as sent, with named parameters
+    pub sql: String,
+    /// SQL as run, with the placeholders of the database
+    pub rewritten_sql: String,
+    /// The sets of parameters the statement was run with, as sent
+    pub parameters: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows_returned: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows_affected: Option<u64>,
+    pub duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The last statements run, oldest first
+pub(crate) struct StatementLog {
+    capacity: usize,
+    statements: Mutex<VecDeque<LoggedStatement>>,
+    next_id: Mutex<u64>,
+}
+
+impl StatementLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            statements: Mutex::new(VecDeque::with_capacity(capacity)),
+            next_id: Mutex::new(0),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Add `statement`, dropping the oldest one if the log is full. Its id is set here.
+    pub(crate) fn record(&self, mut statement: LoggedStatement) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut next_id = self.next_id.lock().unwrap();
+        *next_id += 1;
+        statement.id = *next_id;
+
+        let mut statements = self.statements.lock().unwrap();
+        if statements.len() == self.capacity {
+            statements.pop_front();
+        }
+        statements.push_back(statement);
+    }
+}
+
+/// Whether `sql` mentions `table` as an identifier, quoted or not, possibly qualified
+fn references_table(sql: &str, table: &str) -> bool {
+    sql.split(|c: char| !(c == '_' || c == '$' || c.is_alphanumeric()))
+        .any(|word| word.eq_ignore_ascii_case(table))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StatementFilter {
+    /// Part of the SQL, ignoring case
+    sql: Option<String>,
+    /// Table the SQL refers to
+    table: Option<String>,
+    database: Option<String>,
+    transaction_id: Option<String>,
+    /// Only the statements run at or after this time
+    since: Option<DateTime<Utc>>,
+    /// Only the statements run before this time
+    until: Option<DateTime<Utc>>,
+    /// Only the most recent matching statements
+    limit: Option<usize>,
+}
+
+impl StatementFilter {
+    fn matches(&self, statement: &LoggedStatement) -> bool {
+        let sql = statement.sql.to_lowercase();
+        self.sql
+            .as_ref()
+            .is_none_or(|part| sql.contains(&part.to_lowercase()))
+            && self
+                .table
+                .as_ref()
+                .is_none_or(|table| references_table(&statement.sql, table))
+            && self
+                .database
+                .as_ref()
+                .is_none_or(|d| statement.database.as_ref() == Some(d))
+            && self
+                .transaction_id
+                .as_ref()
+                .is_none_or(|id| statement.transaction_id.as_ref() == Some(id))
+            && self.since.is_none_or(|since| statement.timestamp >= since)
+            && self.until.is_none_or(|until| statement.timestamp < until)
+    }
+}
+
+async fn list_statements(
+    State(log): State<Arc<StatementLog>>,
+    Query(filter): Query<StatementFilter>,
+) -> Json<Vec<LoggedStatement>> {
+    let statements = log.statements.lock().unwrap();
+    let mut matching: Vec<LoggedStatement> = statements
+        .iter()
+        .filter(|statement| filter.matches(statement))
+        .cloned()
+        .collect();
+    if let Some(limit) = filter.limit {
+        matching.drain(..matching.len().saturating_sub(limit));
+    }
+    Json(matching)
+}
+
+async fn clear_statements(State(log): State<Arc<StatementLog>>) -> StatusCode {
+    log.statements.lock().unwrap().clear();
+    StatusCode::NO_CONTENT
+}
+
+/// `/_admin/statements` endpoints searching and clearing the statement log
+pub(crate) fn admin_router(log: Arc<StatementLog>) -> Router {
+    Router::new()
+        .route(
+            "/_admin/statements",
+            get(list_statements).delete(clear_statements),
+        )
+        .with_state(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::Builder;
+    use crate::backend::SqliteBackend;
+    use crate::test_util::call;
+
+    async fn list(app: &Router, query: &str) -> Vec<Value> {
+        let uri = format!("/_admin/statements{query}");
+        let (status, body) = call(app, "GET", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        body.as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn test_references_table() {
+        assert!(references_table("SELECT * FROM users", "users"));
+        assert!(references_table("select * from \"Users\" u", "users"));
+        assert!(references_table("SELECT * FROM app.users WHERE x", "USERS"));
+        assert!(!references_table("SELECT * FROM users_archive", "users"));
+        assert!(!references_table("SELECT user_id FROM t", "users"));
+    }
+
+    #[tokio::test]
+    async fn test_statement_log() {
+        let app = Builder::new()
+            .backend(SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+        let execute = |sql: &str| json!({"database": "app", "sql": sql});
+
+        call(
+            &app,
+            "POST",
+            "/Execute",
+            execute("CREATE TABLE users (name TEXT)"),
+        )
+        .await;
+        let insert = json!({
+            "database": "app",
+            "sql": "INSERT INTO users (name) VALUES (:name)",
+            "parameters": [{"name": "name", "value": {"stringValue": "ann"}}],
+        });
+        call(&app, "POST", "/Execute", insert).await;
+        call(&app, "POST", "/Execute", execute("SELECT * FROM missing")).await;
+
+        let (_, body) = call(
+            &app,
+            "POST",
+            "/BeginTransaction",
+            json!({"database": "app"}),
+        )
+        .await;
+        let id = body["transactionId"].as_str().unwrap().to_string();
+        let select = json!({"sql": "SELECT name FROM users", "transactionId": id});
+        call(&app, "POST", "/Execute", select).await;
+        call(
+            &app,
+            "POST",
+            "/CommitTransaction",
+            json!({"transactionId": id}),
+        )
+        .await;
+
+        let statements = list(&app, "").await;
+        assert_eq!(statements.len(), 4);
+        let ids: Vec<u64> = statements
+            .iter()
+            .map(|s| s["id"].as_u64().unwrap())
+            .collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+
+        let inserts = list(&app, "?sql=insert%20into").await;
+        assert_eq!(inserts.len(), 1);
+        assert_eq!(inserts[0]["database"], "app");
+        assert_eq!(
+            inserts[0]["rewritten_sql"],
+            "INSERT INTO users (name) VALUES (?)"
+        );
+        assert_eq!(
+            inserts[0]["parameters"],
+            json!([[{"name": "name", "value": {"stringValue": "ann"}}]])
+        );
+        assert_eq!(inserts[0]["rows_affected"], 1);
+
+        assert_eq!(list(&app, "?table=users").await.len(), 3);
+        let failed = list(&app, "?table=missing").await;
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0]["error"].as_str().unwrap().contains("missing"));
+
+        let in_transaction = list(&app, &format!("?transaction_id={id}")).await;
+        assert_eq!(in_transaction.len(), 1);
+        assert_eq!(in_transaction[0]["rows_returned"], 1);
+
+        let last = list(&app, "?limit=2").await;
+        assert_eq!(last, statements[2..]);
+        assert!(list(&app, "?until=2000-01-01T00:00:00Z").await.is_empty());
+
+        let (status, _) = call(&app, "DELETE", "/_admin/statements", Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(list(&app, "").await.is_empty());
+    }
+}
@@ -0,0 +1,42 @@
+//! Helpers shared by the tests of the other modules
+
+use axum::Router;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::{Value, json};
+use tower::ServiceExt;
+
+/// Send a request with a JSON `body` to `app`, and get its status and body back. A body that
+/// isn't JSON is returned as a string, and an empty one as null. The AWS error type of the
+/// response, if any, is added to its body as `type`.
+pub(crate) async fn call(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    body: Value,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let error_type = response.headers().get("x-amzn-ErrorType").cloned();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let mut body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into()))
+    };
+    if let Some(error_type) = error_type
+        && !body.is_string()
+    {
+        body["type"] = json!(error_type.to_str().unwrap());
+    }
+    (status, body)
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use log::info;
+
+use crate::api::request_fields;
+use crate::config::{RateLimit, ThrottlingConfig};
+use crate::error::AwsError;
+
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.capacity(),
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.capacity());
+        self.refilled_at = now;
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    global: Option<TokenBucket>,
+    secrets: HashMap<String, TokenBucket>,
+    resources: HashMap<String, TokenBucket>,
+}
+
+/// Rate limits of the Data API requests, by secret and resource ARN
+pub struct Throttler {
+    config: ThrottlingConfig,
+    buckets: Mutex<Buckets>,
+}
+
+impl Throttler {
+    pub fn new(config: ThrottlingConfig) -> Self {
+        let buckets = Buckets {
+            global: config
+                .global
+                .map(|limit| TokenBucket::new(limit, Instant::now())),
+            ..Buckets::default()
+        };
+        Self {
+            config,
+            buckets: Mutex::new(buckets),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        let config = &self.config;
+        config.global.is_some()
+            || config.per_secret.is_some()
+            || config.per_resource.is_some()
+            || !config.overrides.is_empty()
+    }
+
+    /// The limit of `arn`, its override if any
+    fn limit(&self, arn: &str, default: Option<RateLimit>) -> Option<RateLimit> {
+        self.config
+            .overrides
+            .iter()
+            .find(|o| o.arn == arn)
+            .map(|o| o.limit())
+            .or(default)
+    }
+
+    /// Take a token from every bucket applying to a request, unless one of them is empty
+    fn acquire(&self, secret_arn: Option<&str>, resource_arn: Option<&str>, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets {
+            global,
+            secrets,
+            resources,
+        } = &mut *buckets;
+
+        let mut applying = vec![];
+        if let Some(global) = global {
+            applying.push(global);
+        }
+        for (arn, default, buckets) in [
+            (secret_arn, self.config.per_secret, secrets),
+            (resource_arn, self.config.per_resource, resources),
+        ] {
+            if let Some(arn) = arn
+                && let Some(limit) = self.limit(arn, default)
+            {
+                let bucket = buckets
+                    .entry(arn.to_string())
+                    .or_insert_with(|| TokenBucket::new(limit, now));
+                applying.push(bucket);
+            }
+        }
+
+        for bucket in applying.iter_mut() {
+            bucket.refill(now);
+        }
+        if applying.iter().any(|bucket| bucket.tokens < 1.0) {
+            return false;
+        }
+        for bucket in applying {
+            bucket.tokens -= 1.0;
+        }
+        true
+    }
+}
+
+/// Middleware answering `ThrottlingException` to the requests exceeding a rate limit
+pub(crate) async fn throttle(
+    State(throttler): State<Arc<Throttler>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !throttler.is_enabled() {
+        return next.run(request).await;
+    }
+
+    let fields = request_fields(&request);
+    let secret_arn = fields.secret_arn.as_deref();
+    let resource_arn = fields.resource_arn.as_deref();
+
+    if !throttler.acquire(secret_arn, resource_arn, Instant::now()) {
+        info!(
+            "Throttling request for secret {} on {}",
+            secret_arn.unwrap_or("(none)"),
+            resource_arn.unwrap_or("(none)")
+        );
+        return AwsError::new("ThrottlingException", "Rate exceeded").into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use crate::Builder;
+    use crate::backend::SqliteBackend;
+    use crate::config::{Config, RateLimitOverride};
+    use crate::error::ERROR_TYPE_HEADER;
+
+    #[test]
+    fn test_token_buckets() {
+        let throttler = Throttler::new(ThrottlingConfig {
+            global: Some(RateLimit {
+                rate: 100.0,
+                burst: Some(4.0),
+            }),
+            per_secret: Some(RateLimit {
+                rate: 1.0,
+                burst: Some(2.0),
+            }),
+            per_resource: None,
+            overrides: vec![RateLimitOverride {
+                arn: "arn:fast".to_string(),
+                rate: 1000.0,
+                burst: None,
+            }],
+        });
+        let start = Instant::now();
+
+        // Each secret gets its own bucket of 2 requests
+        assert!(throttler.acquire(Some("arn:a"), None, start));
+        assert!(throttler.acquire(Some("arn:a"), None, start));
+        assert!(!throttler.acquire(Some("arn:a"), None, start));
+        assert!(throttler.acquire(Some("arn:b"), None, start));
+
+        // The global bucket of 4 is shared
+        assert!(throttler.acquire(Some("arn:fast"), None, start));
+        assert!(!throttler.acquire(Some("arn:fast"), None, start));
+
+        // Buckets refill at their rate
+        let later = start + Duration::from_secs(1);
+        assert!(throttler.acquire(Some("arn:a"), None, later));
+        assert!(!throttler.acquire(Some("arn:a"), None, later));
+        assert!(throttler.acquire(None, Some("arn:cluster"), later));
+    }
+
+    #[tokio::test]
+    async fn test_throttle() {
+        let config = Config {
+            throttling: ThrottlingConfig {
+                per_secret: Some(RateLimit {
+                    rate: 10.0,
+                    burst: Some(2.0),
+                }),
+                ..ThrottlingConfig::default()
+            },
+            ..Config::default()
+        };
+        let app = Builder::new()
+            .config(config)
+            .backend(SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+        let call = async || {
+            let body = json!({"secretArn": "arn:secret", "sql": "SELECT 1"});
+            let request = Request::post("/Execute")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            let error_type = response.headers().get(ERROR_TYPE_HEADER).cloned();
+            (response.status(), error_type)
+        };
+
+        for _ in 0..2 {
+            assert_eq!(call().await, (StatusCode::OK, None));
+        }
+        let (status, error_type) = call().await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(error_type.unwrap(), "ThrottlingException");
+
+        // A token is back after 100ms
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(call().await, (StatusCode::OK, None));
+    }
+}
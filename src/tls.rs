@@ -0,0 +1,295 @@
+//! HTTPS listener, with the certificate from the configuration or a self-signed one
+
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::ssl::{AlpnError, Ssl, SslAcceptor, SslFiletype, SslMethod, select_next_proto};
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+};
+use openssl::x509::{X509, X509NameBuilder};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_openssl::SslStream;
+
+use crate::config::TlsConfig;
+
+/// How long a client has to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Protocols offered to clients through ALPN. axum is built without HTTP/2.
+const ALPN_PROTOCOLS: &[u8] = b"\x08http/1.1";
+
+/// How long the self-signed certificates are valid
+const SELF_SIGNED_DAYS: u32 = 3650;
+
+/// The acceptor of the HTTPS connections, with the certificate and key of `config`,
+/// generating them first if they're self-signed and missing
+pub(crate) fn acceptor(config: &TlsConfig) -> Result<SslAcceptor> {
+    if config.self_signed && !(config.certificate_path.is_file() && config.key_path.is_file()) {
+        write_self_signed(config)?;
+    }
+
+    let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+    acceptor
+        .set_certificate_chain_file(&config.certificate_path)
+        .with_context(|| format!("Invalid certificate {}", config.certificate_path.display()))?;
+    acceptor
+        .set_private_key_file(&config.key_path, SslFiletype::PEM)
+        .with_context(|| format!("Invalid private key {}", config.key_path.display()))?;
+    acceptor
+        .check_private_key()
+        .context("The private key doesn't match the certificate")?;
+    acceptor.set_alpn_select_callback(|_, client| {
+        select_next_proto(ALPN_PROTOCOLS, client).ok_or(AlpnError::NOACK)
+    });
+    Ok(acceptor.build())
+}
+
+/// Generate a self-signed certificate for `config.hostnames`, and write it and its key
+fn write_self_signed(config: &TlsConfig) -> Result<()> {
+    let (certificate, key) = self_signed(&config.hostnames)?;
+    for path in [&config.certificate_path, &config.key_path] {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+    }
+    write_file(&config.certificate_path, &certificate.to_pem()?, false)?;
+    write_file(&config.key_path, &key.private_key_to_pem_pkcs8()?, true)?;
+    info!(
+        "Wrote a self-signed certificate for {} to {}",
+        config.hostnames.join(", "),
+        config.certificate_path.display()
+    );
+    Ok(())
+}
+
+/// Write `contents` to `path`, only readable by the current user if `private`
+fn write_file(path: &Path, contents: &[u8], private: bool) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    std::io::Write::write_all(&mut file, contents)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// A self-signed certificate valid for `hostnames`, which may be IP addresses, and its key
+fn self_signed(hostnames: &[String]) -> Result<(X509, PKey<Private>)> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, "rds-lite")?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+    let serial = serial.to_asn1_integer()?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(SELF_SIGNED_DAYS)?;
+
+    let mut alternative_names = SubjectAlternativeName::new();
+    for hostname in hostnames {
+        match hostname.parse::<IpAddr>() {
+            Ok(_) => alternative_names.ip(hostname),
+            Err(_) => alternative_names.dns(hostname),
+        };
+    }
+
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&key)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+    builder.append_extension(BasicConstraints::new().critical().build()?)?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .digital_signature()
+            .key_encipherment()
+            .build()?,
+    )?;
+    builder.append_extension(ExtendedKeyUsage::new().server_auth().build()?)?;
+    let alternative_names = alternative_names.build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(alternative_names)?;
+    builder.sign(&key, MessageDigest::sha256())?;
+    Ok((builder.build(), key))
+}
+
+/// Accepts TLS connections for `axum::serve`. The handshakes run in the background, so
+/// that a slow or failing client doesn't hold up the others.
+pub(crate) struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(SslStream<TcpStream>, SocketAddr)>,
+    accepting: JoinHandle<()>,
+}
+
+impl TlsListener {
+    pub(crate) fn new(listener: TcpListener, acceptor: SslAcceptor) -> Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = Arc::new(acceptor);
+        let (sender, connections) = mpsc::channel(64);
+        let accepting = tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Failed to accept a connection: {e}");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(&acceptor, stream))
+                        .await
+                    {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, addr)).await;
+                        }
+                        Ok(Err(e)) => warn!("TLS handshake with {addr} failed: {e}"),
+                        Err(_) => warn!("TLS handshake with {addr} timed out"),
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            local_addr,
+            connections,
+            accepting,
+        })
+    }
+}
+
+async fn handshake(acceptor: &SslAcceptor, stream: TcpStream) -> Result<SslStream<TcpStream>> {
+    let ssl = Ssl::new(acceptor.context())?;
+    let mut stream = SslStream::new(ssl, stream)?;
+    Pin::new(&mut stream).accept().await?;
+    Ok(stream)
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = SslStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accepting task never stops on its own
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+impl Drop for TlsListener {
+    /// Stop accepting connections once the server stops
+    fn drop(&mut self) {
+        self.accepting.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ssl::{SslConnector, SslVerifyMode};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::Builder;
+    use crate::backend::SqliteBackend;
+
+    async fn post(addr: SocketAddr, certificate: &Path, body: &str) -> Result<String> {
+        let mut connector = SslConnector::builder(SslMethod::tls_client())?;
+        connector.set_ca_file(certificate)?;
+        connector.set_verify(SslVerifyMode::PEER);
+        let ssl = connector.build().configure()?.into_ssl("localhost")?;
+
+        let mut stream = SslStream::new(ssl, TcpStream::connect(addr).await?)?;
+        Pin::new(&mut stream).connect().await?;
+        let request = format!(
+            "POST /Execute HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(response)
+    }
+
+    #[tokio::test]
+    async fn test_self_signed() {
+        let directory = std::env::temp_dir().join(format!("rds-lite-tls-{}", std::process::id()));
+        let config = TlsConfig {
+            port: None,
+            certificate_path: directory.join("cert.pem"),
+            key_path: directory.join("private/key.pem"),
+            self_signed: true,
+            hostnames: vec!["localhost".to_string()],
+        };
+        let server_acceptor = acceptor(&config).unwrap();
+        let certificate = std::fs::read(&config.certificate_path).unwrap();
+        // The certificate is kept across restarts, so that clients keep trusting it
+        acceptor(&config).unwrap();
+        assert_eq!(
+            std::fs::read(&config.certificate_path).unwrap(),
+            certificate
+        );
+
+        let app = Builder::new()
+            .backend(SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener = TlsListener::new(listener, server_acceptor).unwrap();
+        let addr = listener.local_addr;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let response = post(addr, &config.certificate_path, r#"{"sql": "SELECT 1"}"#)
+            .await
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains(r#""records":[[{"longValue":1}]]"#));
+
+        // Clients not trusting the certificate can't connect
+        let other = TlsConfig {
+            certificate_path: directory.join("other.pem"),
+            key_path: directory.join("other-key.pem"),
+            ..config.clone()
+        };
+        acceptor(&other).unwrap();
+        assert!(post(addr, &other.certificate_path, "{}").await.is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
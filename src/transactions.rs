@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+};
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::Serialize;
+
+use crate::aws::SqlParameterDef;
+use crate::backend::Transaction;
+use crate::cluster::Cluster;
+use crate::config::TransactionsConfig;
+use crate::error::{AwsError, query_error_response};
+use crate::metrics;
+use crate::query::{self, QueryError, QueryOutput};
+use crate::statements::StatementLog;
+
+/// What is known about an open transaction, readable while it runs a statement
+struct TransactionInfo {
+    cluster: Arc<Cluster>,
+    database: Option<String>,
+    secret_arn: Option<String>,
+    connection_id: Option<u64>,
+    started: Instant,
+    started_at: DateTime<Utc>,
+    last_used: Instant,
+    last_statement: Option<String>,
+}
+
+impl TransactionInfo {
+    /// Why the transaction must be rolled back at `now`, if it must
+    fn expiry(&self, config: &TransactionsConfig, now: Instant) -> Option<&'static str> {
+        if now.duration_since(self.started) >= Duration::from_millis(config.max_lifetime_ms) {
+            Some("max_lifetime")
+        } else if now.duration_since(self.last_used)
+            >= Duration::from_millis(config.idle_timeout_ms)
+        {
+            Some("idle")
+        } else {
+            None
+        }
+    }
+}
+
+/// A transaction started with `BeginTransaction` and not ended yet
+struct OpenTransaction {
+    info: Mutex<TransactionInfo>,
+    /// Locked while a statement runs in the transaction, and taken when it ends, for the
+    /// requests that were waiting for it
+    transaction: tokio::sync::Mutex<Option<Box<dyn Transaction>>>,
+}
+
+#[derive(Debug)]
+pub(crate) enum TransactionError {
+    /// The transaction never existed, ended or expired
+    NotFound(String),
+    Query(QueryError),
+}
+
+impl IntoResponse for TransactionError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::NotFound(id) => AwsError::new(
+                "TransactionNotFoundException",
+                format!("Transaction {id} is not found"),
+            )
+            .into_response(),
+            Self::Query(e) => query_error_response(e),
+        }
+    }
+}
+
+/// The open transactions, by id
+pub(crate) struct Transactions {
+    config: TransactionsConfig,
+    open: Mutex<HashMap<String, Arc<OpenTransaction>>>,
+    reaper: Once,
+    log: Arc<StatementLog>,
+}
+
+impl Transactions {
+    pub(crate) fn new(config: TransactionsConfig, log: Arc<StatementLog>) -> Self {
+        Self {
+            config,
+            open: Mutex::new(HashMap::new()),
+            reaper: Once::new(),
+            log,
+        }
+    }
+
+    /// Start a transaction on `cluster` and return its id
+    pub(crate) async fn begin(
+        self: &Arc<Self>,
+        cluster: Arc<Cluster>,
+        database: Option<&str>,
+        schema: Option<&str>,
+        secret_arn: Option<&str>,
+    ) -> Result<String, QueryError> {
+        self.start_reaper();
+
+        let transaction = cluster.backend.begin_transaction(database, schema).await?;
+        let id =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+        info!("Started transaction {id} on cluster '{}'", cluster.name);
+        metrics::transaction_started(&cluster.name);
+
+        let now = Instant::now();
+        let info = TransactionInfo {
+            cluster,
+            database: database.map(str::to_string),
+            secret_arn: secret_arn.map(str::to_string),
+            connection_id: transaction.connection_id(),
+            started: now,
+            started_at: Utc::now(),
+            last_used: now,
+            last_statement: None,
+        };
+        let open = OpenTransaction {
+            info: Mutex::new(info),
+            transaction: tokio::sync::Mutex::new(Some(transaction)),
+        };
+        self.open.lock().unwrap().insert(id.clone(), Arc::new(open));
+        Ok(id)
+    }
+
+    fn get(&self, id: &str) -> Result<Arc<OpenTransaction>, TransactionError> {
+        self.open
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| TransactionError::NotFound(id.to_string()))
+    }
+
+    /// Run `sql` in the transaction `id`, once for each set of parameters in `params`
+    pub(crate) async fn run_query(
+        &self,
+        id: &str,
+        sql: &str,
+        params: Vec<Vec<SqlParameterDef>>,
+    ) -> Result<QueryOutput, TransactionError> {
+        let open = self.get(id)?;
+        let mut transaction = open.transaction.lock().await;
+        let Some(transaction) = transaction.as_mut() else {
+            return Err(TransactionError::NotFound(id.to_string()));
+        };
+
+        let (cluster, database) = {
+            let mut info = open.info.lock().unwrap();
+            info.last_statement = Some(sql.to_string());
+            (info.cluster.clone(), info.database.clone())
+        };
+        let result = query::run_query_in_transaction(
+            &cluster,
+            transaction.as_mut(),
+            id,
+            database,
+            sql,
+            params,
+            &self.log,
+        )
+        .await;
+        open.info.lock().unwrap().last_used = Instant::now();
+        result.map_err(TransactionError::Query)
+    }
+
+    /// Commit or roll back the transaction `id`
+    pub(crate) async fn end(&self, id: &str, commit: bool) -> Result<(), TransactionError> {
+        let not_found = || TransactionError::NotFound(id.to_string());
+        let open = self.open.lock().unwrap().remove(id).ok_or_else(not_found)?;
+        let transaction = open.transaction.lock().await.take().ok_or_else(not_found)?;
+        metrics::transaction_ended(&open.info.lock().unwrap().cluster.name, None);
+
+        let result = if commit {
+            transaction.commit().await
+        } else {
+            transaction.rollback().await
+        };
+        result.map_err(|e| TransactionError::Query((StatusCode::INTERNAL_SERVER_ERROR, e)))
+    }
+
+    /// Periodically roll back the expired transactions, for as long as `self` is used
+    fn start_reaper(self: &Arc<Self>) {
+        self.reaper.call_once(|| {
+            let shortest = self.config.idle_timeout_ms.min(self.config.max_lifetime_ms);
+            let period = Duration::from_millis((shortest / 10).clamp(10, 1000));
+            let transactions = Arc::downgrade(self);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(period);
+                loop {
+                    interval.tick().await;
+                    let Some(transactions) = transactions.upgrade() else {
+                        break;
+                    };
+                    transactions.expire(Instant::now()).await;
+                }
+            });
+        });
+    }
+
+    async fn expire(&self, now: Instant) {
+        let mut expired = vec![];
+        self.open.lock().unwrap().retain(|id, open| {
+            // A transaction running a statement isn't idle, and is checked again once it's done
+            let reason = match open.transaction.try_lock() {
+                Ok(_) => open.info.lock().unwrap().expiry(&self.config, now),
+                Err(_) => None,
+            };
+            if let Some(reason) = reason {
+                expired.push((id.clone(), open.clone(), reason));
+            }
+            reason.is_none()
+        });
+
+        for (id, open, reason) in expired {
+            let Some(transaction) = open.transaction.lock().await.take() else {
+                continue;
+            };
+            let cluster = open.info.lock().unwrap().cluster.name.clone();
+            warn!("Rolling back transaction {id} of cluster '{cluster}', expired ({reason})");
+            metrics::transaction_ended(&cluster, Some(reason));
+            if let Err(e) = transaction.rollback().await {
+                error!("Failed to roll back transaction {id}: {e:#}");
+            }
+        }
+    }
+
+    /// Roll back all the open transactions, when the server shuts down
+    pub(crate) async fn rollback_all(&self) {
+        let open: Vec<_> = self.open.lock().unwrap().drain().collect();
+        for (id, open) in open {
+            // Waits for a statement still running, if the request running it wasn't cancelled
+            let Some(transaction) = open.transaction.lock().await.take() else {
+                continue;
+            };
+            let cluster = open.info.lock().unwrap().cluster.name.clone();
+            warn!("Rolling back transaction {id} of cluster '{cluster}' on shutdown");
+            metrics::transaction_ended(&cluster, None);
+            if let Err(e) = transaction.rollback().await {
+                error!("Failed to roll back transaction {id}: {e:#}");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TransactionDef {
+    id: String,
+    cluster: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    database: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret_arn: Option<String>,
+    /// MySQL `CONNECTION_ID()` or PostgreSQL `pg_backend_pid()` of the connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connection_id: Option<u64>,
+    started_at: DateTime<Utc>,
+    age_ms: u128,
+    idle_ms: u128,
+    /// Whether a statement is running in the transaction
+    running: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_statement: Option<String>,
+}
+
+async fn list_transactions(
+    State(transactions): State<Arc<Transactions>>,
+) -> Json<Vec<TransactionDef>> {
+    let now = Instant::now();
+    let mut list: Vec<TransactionDef> = transactions
+        .open
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, open)| {
+            let running = open.transaction.try_lock().is_err();
+            let info = open.info.lock().unwrap();
+            TransactionDef {
+                id: id.clone(),
+                cluster: info.cluster.name.clone(),
+                database: info.database.clone(),
+                secret_arn: info.secret_arn.clone(),
+                connection_id: info.connection_id,
+                started_at: info.started_at,
+                age_ms: now.duration_since(info.started).as_millis(),
+                idle_ms: if running {
+                    0
+                } else {
+                    now.duration_since(info.last_used).as_millis()
+                },
+                running,
+                last_statement: info.last_statement.clone(),
+            }
+        })
+        .collect();
+    list.sort_by_key(|transaction| transaction.started_at);
+    Json(list)
+}
+
+async fn delete_transaction(
+    State(transactions): State<Arc<Transactions>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let not_found = || (StatusCode::NOT_FOUND, format!("No transaction {id}"));
+    let open = transactions.get(&id).map_err(|_| not_found())?;
+    // A statement can't be interrupted, e.g. while it waits for a lock
+    let Ok(mut transaction) = open.transaction.try_lock() else {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Transaction {id} is running a statement"),
+        ));
+    };
+    let transaction = transaction.take().ok_or_else(not_found)?;
+    transactions.open.lock().unwrap().remove(&id);
+
+    let cluster = open.info.lock().unwrap().cluster.name.clone();
+    info!("Rolling back transaction {id} of cluster '{cluster}' on request");
+    metrics::transaction_ended(&cluster, None);
+    transaction.rollback().await.map_err(|e| {
+        error!("Failed to roll back transaction {id}: {e:#}");
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}"))
+    })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `/_admin/transactions` endpoints listing the open transactions and rolling them back
+pub(crate) fn admin_router(transactions: Arc<Transactions>) -> Router {
+    Router::new()
+        .route("/_admin/transactions", get(list_transactions))
+        .route("/_admin/transactions/{id}", delete(delete_transaction))
+        .with_state(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::Builder;
+    use crate::backend::SqliteBackend;
+    use crate::config::Config;
+    use crate::test_util::call;
+
+    async fn begin(app: &axum::Router) -> String {
+        let (status, body) = call(app, "POST", "/BeginTransaction", json!({})).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        body["transactionId"].as_str().unwrap().to_string()
+    }
+
+    async fn count(app: &axum::Router) -> Value {
+        let (_, body) = call(
+            app,
+            "POST",
+            "/Execute",
+            json!({"sql": "SELECT count(*) FROM t"}),
+        )
+        .await;
+        body["records"][0][0]["longValue"].clone()
+    }
+
+    #[tokio::test]
+    async fn test_transactions() {
+        let app = Builder::new()
+            .config(Config {
+                transactions: TransactionsConfig {
+                    idle_timeout_ms: 100,
+                    max_lifetime_ms: 60_000,
+                },
+                ..Config::default()
+            })
+            .backend(SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+        call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"sql": "CREATE TABLE t (x INTEGER)"}),
+        )
+        .await;
+        let insert = |id: &str| json!({"sql": "INSERT INTO t VALUES (1)", "transactionId": id});
+
+        let id = begin(&app).await;
+        let (status, _) = call(&app, "POST", "/Execute", insert(&id)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = call(
+            &app,
+            "POST",
+            "/CommitTransaction",
+            json!({"transactionId": id}),
+        )
+        .await;
+        assert_eq!(body["transactionStatus"], "Transaction Committed");
+        assert_eq!(count(&app).await, 1);
+
+        let id = begin(&app).await;
+        call(&app, "POST", "/BatchExecute", insert(&id)).await;
+        let (_, body) = call(
+            &app,
+            "POST",
+            "/RollbackTransaction",
+            json!({"transactionId": id}),
+        )
+        .await;
+        assert_eq!(body["transactionStatus"], "Rollback Complete");
+        assert_eq!(count(&app).await, 1);
+
+        // Ended transactions can't be used anymore
+        let (status, body) = call(&app, "POST", "/Execute", insert(&id)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["type"], "TransactionNotFoundException");
+        assert_eq!(body["message"], format!("Transaction {id} is not found"));
+
+        // Idle transactions are rolled back
+        let id = begin(&app).await;
+        call(&app, "POST", "/Execute", insert(&id)).await;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/CommitTransaction",
+            json!({"transactionId": id}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+        assert_eq!(count(&app).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_admin_transactions() {
+        let app = Builder::new()
+            .backend(SqliteBackend::in_memory())
+            .router()
+            .unwrap();
+        call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"sql": "CREATE TABLE t (x INTEGER)"}),
+        )
+        .await;
+
+        let body = json!({"database": "default", "secretArn": "arn:secret"});
+        let (_, body) = call(&app, "POST", "/BeginTransaction", body).await;
+        let id = body["transactionId"].as_str().unwrap().to_string();
+        let insert = json!({"sql": "INSERT INTO t VALUES (1)", "transactionId": id});
+        call(&app, "POST", "/Execute", insert.clone()).await;
+
+        let (status, list) = call(&app, "GET", "/_admin/transactions", Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(list[0]["id"], json!(id));
+        assert_eq!(list[0]["cluster"], "default");
+        assert_eq!(list[0]["database"], "default");
+        assert_eq!(list[0]["secret_arn"], "arn:secret");
+        assert_eq!(list[0]["last_statement"], "INSERT INTO t VALUES (1)");
+        assert_eq!(list[0]["running"], false);
+        assert!(list[0]["age_ms"].is_u64());
+
+        let uri = format!("/_admin/transactions/{id}");
+        let (status, _) = call(&app, "DELETE", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = call(&app, "DELETE", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = call(&app, "POST", "/Execute", insert).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // The connection was released and the insert rolled back
+        let (_, body) = call(
+            &app,
+            "POST",
+            "/Execute",
+            json!({"sql": "SELECT count(*) FROM t"}),
+        )
+        .await;
+        assert_eq!(body["records"], json!([[{"longValue": 0}]]));
+        let (_, list) = call(&app, "GET", "/_admin/transactions", Value::Null).await;
+        assert_eq!(list, json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_rollback_all() {
+        let cluster = Arc::new(Cluster {
+            name: "default".to_string(),
+            resource_arn: None,
+            backend: Arc::new(SqliteBackend::in_memory()),
+        });
+        let log = Arc::new(StatementLog::new(0));
+        let transactions = Arc::new(Transactions::new(
+            TransactionsConfig::default(),
+            log.clone(),
+        ));
+        let sql = "CREATE TABLE t (x INTEGER)";
+        query::run_query(&cluster, None, None, sql, vec![vec![]], &log)
+            .await
+            .unwrap();
+
+        let id = transactions
+            .begin(cluster.clone(), None, None, None)
+            .await
+            .unwrap();
+        let sql = "INSERT INTO t VALUES (1)";
+        transactions
+            .run_query(&id, sql, vec![vec![]])
+            .await
+            .unwrap();
+        transactions.rollback_all().await;
+
+        let result = transactions.run_query(&id, "SELECT 1", vec![vec![]]).await;
+        assert!(matches!(result, Err(TransactionError::NotFound(_))));
+        // The in-memory database has a single connection, which was given back
+        let sql = "SELECT count(*) FROM t";
+        let output = query::run_query(&cluster, None, None, sql, vec![vec![]], &log)
+            .await
+            .unwrap();
+        let records = serde_json::to_value(output.left().unwrap().rows).unwrap();
+        assert_eq!(records, json!([[{"longValue": 0}]]));
+    }
+}